@@ -0,0 +1,53 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use s4::io::compress::compress;
+use s4::io::decompress::{decompress, decompress_slice};
+
+/// Tiles of (height, ground type, flags) resembling a landscape segment
+fn landscape(width: usize) -> Vec<u8> {
+    let mut state: u32 = 0x5EED;
+    let mut data = Vec::with_capacity(width * width * 4);
+    for y in 0..width {
+        for x in 0..width {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let noise = (state >> 16) as u8;
+            let height = ((x / 16 + y / 16) as u8).wrapping_add(noise & 3);
+            let ground = if (x / 64 + y / 32) % 3 == 0 { 16 } else { 0 };
+            data.extend_from_slice(&[height, ground, noise & noise >> 5, 0]);
+        }
+    }
+    data
+}
+
+/// Skewed noise, leaving little for back references
+fn noise(len: usize) -> Vec<u8> {
+    let mut state: u32 = 0xC0FFEE;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let value = (state >> 16) as u8;
+            value & (value >> 3)
+        })
+        .collect()
+}
+
+fn bench_decompress(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decompress");
+
+    for (name, data) in [("landscape", landscape(512)), ("noise", noise(1 << 20))] {
+        let compressed = compress(&data).unwrap();
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &compressed,
+            |b, input| b.iter(|| decompress(&mut &input[..]).unwrap()),
+        );
+        group.bench_with_input(BenchmarkId::new("slice", name), &compressed, |b, input| {
+            b.iter(|| decompress_slice(input).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_decompress);
+criterion_main!(benches);
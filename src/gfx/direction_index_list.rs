@@ -1,11 +1,12 @@
 use byteorder::LittleEndian;
 use byteorder::ReadBytesExt;
-use std::fs::File;
-use std::fs::OpenOptions;
+use std::fs;
 use std::io;
-use std::io::{BufReader, Read};
+use std::io::Read;
 use std::path::Path;
 
+use crate::io::vfs::Vfs;
+
 #[derive(Debug)]
 pub struct DirectionIndexList {
     indices: Vec<u32>,
@@ -24,14 +25,20 @@ impl DirectionIndexList {
         Ok(direction_index_list)
     }
     pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let file = OpenOptions::new().read(true).open(path.as_ref())?;
-        let mut reader = BufReader::<File>::new(file);
+        Self::from_bytes(&fs::read(path)?)
+    }
+    pub fn from_vfs<V: Vfs + ?Sized, P: AsRef<Path>>(vfs: &V, path: P) -> io::Result<Self> {
+        Self::from_bytes(&vfs.read(path.as_ref())?)
+    }
+    /// Contents of a file, a header of 5 fields followed by the data
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = bytes;
 
-        let _magic = reader.read_u32::<LittleEndian>().unwrap();
-        let _unknown0 = reader.read_u32::<LittleEndian>().unwrap();
-        let _unknown1 = reader.read_u32::<LittleEndian>().unwrap();
-        let _unknown2 = reader.read_u32::<LittleEndian>().unwrap();
-        let _unknown3 = reader.read_u32::<LittleEndian>().unwrap();
+        let _magic = reader.read_u32::<LittleEndian>()?;
+        let _unknown0 = reader.read_u32::<LittleEndian>()?;
+        let _unknown1 = reader.read_u32::<LittleEndian>()?;
+        let _unknown2 = reader.read_u32::<LittleEndian>()?;
+        let _unknown3 = reader.read_u32::<LittleEndian>()?;
 
         Self::from_reader(reader)
     }
@@ -1,9 +1,11 @@
 use byteorder::{LittleEndian as LE, ReadBytesExt};
-use std::fs::{File, OpenOptions};
+use std::fs;
 use std::io;
-use std::io::{BufReader, Read};
+use std::io::Read;
 use std::path::Path;
 
+use crate::io::vfs::Vfs;
+
 #[derive(Debug, Default)]
 pub struct Graphic {
     width: u16,
@@ -47,13 +49,20 @@ impl Graphic {
             .checked_mul(4)
             .unwrap();
         graphic.data.resize(data_length, 0);
-        reader.read(&mut graphic.data)?; // TODO: Why does read_exact panic here?
+        // TODO: Why does read_exact fail here? Missing data stays 0 for now.
+        let _read = reader.read(&mut graphic.data)?;
 
         Ok(graphic)
     }
     pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let file = OpenOptions::new().read(true).open(path.as_ref())?;
-        let mut reader = BufReader::<File>::new(file);
+        Graphic::from_bytes(&fs::read(path)?)
+    }
+    pub fn from_vfs<V: Vfs + ?Sized, P: AsRef<Path>>(vfs: &V, path: P) -> io::Result<Self> {
+        Graphic::from_bytes(&vfs.read(path.as_ref())?)
+    }
+    /// Contents of a file, a header of 5 fields followed by the data
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = bytes;
 
         let _magic = reader.read_u32::<LE>()?;
         let _unknown0 = reader.read_u32::<LE>()?;
@@ -1,9 +1,9 @@
-mod direction_index_list;
+pub mod direction_index_list;
 pub mod graphic;
-mod graphic_index_list;
-mod job_index_list;
-mod palette;
-mod palette_index_list;
+pub mod graphic_index_list;
+pub mod job_index_list;
+pub mod palette;
+pub mod palette_index_list;
 
 use std::io;
 use super::io::archive::Archive;
@@ -33,6 +33,15 @@ pub struct Settler {
     job: Option<Job>,
 }
 
+impl Settler {
+    pub fn tribe(&self) -> &Tribe {
+        &self.tribe
+    }
+    pub fn job(&self) -> Option<&Job> {
+        self.job.as_ref()
+    }
+}
+
 pub enum Tribe {
     Roman, Viking, Mayan, Dark
 }
@@ -100,9 +109,12 @@ pub enum Kind {
 pub fn load(_kind: Kind) -> io::Result<Resource> {
 
     // gfx.lib stores a register of all available graphical assets
-    let _archive = Archive::new("g:\\GOG\\Settlers 4 Gold\\gfx.lib").unwrap();
+    let _archive = Archive::new("g:\\GOG\\Settlers 4 Gold\\gfx.lib")?;
 
-    todo!()
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "loading resources is not implemented yet",
+    ))
 }
 
 #[cfg(test)]
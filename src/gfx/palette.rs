@@ -1,9 +1,11 @@
 use byteorder::{LittleEndian as LE, ReadBytesExt};
-use std::fs::{File, OpenOptions};
+use std::fs;
 use std::io;
-use std::io::{BufReader, Read};
+use std::io::Read;
 use std::path::Path;
 
+use crate::io::vfs::Vfs;
+
 // .p24 .p25 .p26 .p44 .p45 .p46
 // pa5 pa6
 
@@ -23,8 +25,14 @@ impl Palette {
         Ok(palette)
     }
     pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let file = OpenOptions::new().read(true).open(path.as_ref())?;
-        let mut reader = BufReader::<File>::new(file);
+        Palette::from_bytes(&fs::read(path)?)
+    }
+    pub fn from_vfs<V: Vfs + ?Sized, P: AsRef<Path>>(vfs: &V, path: P) -> io::Result<Self> {
+        Palette::from_bytes(&vfs.read(path.as_ref())?)
+    }
+    /// Contents of a file, a header of 5 fields followed by the data
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = bytes;
 
         let _magic = reader.read_u32::<LE>()?;
         let _unknown0 = reader.read_u32::<LE>()?;
@@ -34,4 +42,39 @@ impl Palette {
 
         Palette::from_reader(reader)
     }
+    /// 16 bit colors as stored
+    pub fn colors(&self) -> &[u16] {
+        &self.colors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::io::archive::{Archive, ArchiveBuilder};
+    use crate::io::vfs::MemoryFs;
+
+    #[test]
+    fn palette_from_vfs() {
+        let mut file = vec![0; 20];
+        file.extend_from_slice(&[0x00, 0xF8, 0xE0, 0x07, 0x1F, 0x00]);
+
+        let mut memory = MemoryFs::new();
+        memory.insert("Gfx/Pal/0.p46", file.clone());
+        let mut builder = ArchiveBuilder::new(Vec::new());
+        builder.add("gfx/pal/0.p46", &file, true).unwrap();
+        let archive = Archive::from_reader(io::Cursor::new(builder.finish().unwrap())).unwrap();
+
+        let filesystems: [&dyn Vfs; 2] = [&memory, &archive];
+        for vfs in filesystems {
+            let palette = Palette::from_vfs(vfs, "gfx\\pal\\0.p46").unwrap();
+            assert_eq!(palette.colors(), [0xF800, 0x07E0, 0x001F]);
+            let e = Palette::from_vfs(vfs, "gfx/pal/1.p46").unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        }
+
+        let e = Palette::from_bytes(&file[..12]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
 }
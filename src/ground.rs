@@ -1,9 +1,10 @@
-
 use byteorder::{ByteOrder, LittleEndian};
 use num_enum::TryFromPrimitive;
 use std::convert::TryFrom;
+use std::io;
+use std::ops::{Index, IndexMut};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Ground {
     pub height: u8,
     pub ground_type: GroundType,
@@ -11,17 +12,104 @@ pub struct Ground {
 }
 
 impl Ground {
-    pub fn from_le_bytes(bytes: &[u8]) -> Result<Self, String> {
+    /// Length of a tile in the Ground segment
+    pub const SIZE: usize = 4;
+
+    pub fn from_le_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < Ground::SIZE {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
         Ok(Ground {
             height: bytes[0],
-            ground_type: GroundType::try_from(bytes[1]).unwrap(),
+            ground_type: GroundType::try_from(bytes[1]).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown ground type {}", bytes[1]),
+                )
+            })?,
             flags: LittleEndian::read_u16(&bytes[2..4]),
         })
     }
 }
 
+/// Tiles of a square map, row by row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Landscape {
+    size: u32,
+    tiles: Vec<Ground>,
+}
+
+impl Landscape {
+    /// Read size × size tiles, e.g. with the size from `Info::map_size`
+    pub fn from_le_bytes(bytes: &[u8], size: u32) -> io::Result<Self> {
+        // a corrupt size must not wrap around to the length of the tiles
+        let len = (size as usize)
+            .checked_mul(size as usize)
+            .and_then(|count| count.checked_mul(Ground::SIZE));
+        if len != Some(bytes.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "ground of {} bytes doesn't match a map size of {}",
+                    bytes.len(),
+                    size
+                ),
+            ));
+        }
+
+        let tiles: Vec<Ground> = bytes
+            .chunks_exact(Ground::SIZE)
+            .map(Ground::from_le_bytes)
+            .collect::<io::Result<_>>()?;
+
+        Ok(Landscape { size, tiles })
+    }
+    /// Number of tiles along each side
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+    /// Tile at column x of row y, `None` outside the map
+    pub fn get(&self, x: u32, y: u32) -> Option<&Ground> {
+        self.position(x, y).map(|i| &self.tiles[i])
+    }
+    pub fn get_mut(&mut self, x: u32, y: u32) -> Option<&mut Ground> {
+        self.position(x, y).map(|i| &mut self.tiles[i])
+    }
+    pub fn rows(&self) -> impl Iterator<Item = &[Ground]> {
+        // chunks_exact panics on 0, an empty map has no rows either way
+        self.tiles.chunks_exact((self.size as usize).max(1))
+    }
+    /// All tiles, row by row
+    pub fn tiles(&self) -> &[Ground] {
+        &self.tiles
+    }
+    fn position(&self, x: u32, y: u32) -> Option<usize> {
+        if x < self.size && y < self.size {
+            Some(y as usize * self.size as usize + x as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// Tile at (x, y), panics outside the map
+impl Index<(u32, u32)> for Landscape {
+    type Output = Ground;
+
+    fn index(&self, (x, y): (u32, u32)) -> &Ground {
+        self.get(x, y).expect("position outside the landscape")
+    }
+}
+
+impl IndexMut<(u32, u32)> for Landscape {
+    fn index_mut(&mut self, (x, y): (u32, u32)) -> &mut Ground {
+        self.get_mut(x, y).expect("position outside the landscape")
+    }
+}
+
 #[repr(u8)]
-#[derive(Debug, Clone, TryFromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
 pub enum GroundType {
     Water1 = 0,
     Water2,
@@ -72,3 +160,46 @@ pub enum GroundType {
 
     Glitched = 250,
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn landscape() {
+        let bytes: Vec<u8> = (0..9u8)
+            .flat_map(|i| [i, GroundType::Grass as u8, i, 0])
+            .collect();
+        let mut landscape = Landscape::from_le_bytes(&bytes, 3).unwrap();
+        assert_eq!(landscape.size(), 3);
+
+        assert_eq!(landscape[(2, 1)].height, 5);
+        assert_eq!(landscape[(2, 1)].ground_type, GroundType::Grass);
+        assert_eq!(landscape.get(3, 0), None);
+        landscape[(0, 2)].ground_type = GroundType::Snow;
+        assert_eq!(landscape.get(0, 2).unwrap().ground_type, GroundType::Snow);
+
+        let heights: Vec<Vec<u8>> = landscape
+            .rows()
+            .map(|row| row.iter().map(|tile| tile.height).collect())
+            .collect();
+        assert_eq!(heights, [[0, 1, 2], [3, 4, 5], [6, 7, 8]]);
+    }
+
+    #[test]
+    fn invalid_landscape() {
+        let mut bytes = [0, GroundType::Grass as u8, 0, 0].repeat(4);
+        let e = Landscape::from_le_bytes(&bytes, 3).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        // sizes whose tile count overflows instead of wrapping to 0
+        for size in [1 << 31, u32::MAX] {
+            let e = Landscape::from_le_bytes(&[], size).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        }
+
+        bytes[5] = 8;
+        let e = Landscape::from_le_bytes(&bytes, 2).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
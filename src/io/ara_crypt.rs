@@ -1,5 +1,10 @@
-/// ara crypt
-/// implements a liner feedback shift register (LFSR) encryption
+//! ara crypt
+//! implements a liner feedback shift register (LFSR) encryption
+
+use std::io::{Read, Result, Write};
+
+/// Key of the 24 byte segment headers in map files, ASCII "012345678901"
+pub const MAP_HEADER_KEY: [u32; 3] = [0x30313233, 0x34353637, 0x38393031];
 
 pub struct AraCrypt {
     keys: [u32; 3],
@@ -24,6 +29,8 @@ impl AraCrypt {
         self.key_c = self.keys[2];
     }
 
+    /// Next 8 bits of the keystream, in the low byte of the result
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> u32 {
         let mut bit_a: u32 = self.key_b & 1;
         let mut bit_b: u32 = self.key_c & 1;
@@ -70,6 +77,91 @@ impl AraCrypt {
 
         next_key
     }
+
+    /// Overwrite buf with the next buf.len() bytes of the keystream
+    pub fn fill_keystream(&mut self, buf: &mut [u8]) {
+        buf.iter_mut().for_each(|x| *x = self.next() as u8);
+    }
+
+    /// XOR buf with the next buf.len() bytes of the keystream
+    pub fn apply_keystream(&mut self, buf: &mut [u8]) {
+        buf.iter_mut().for_each(|x| *x ^= self.next() as u8);
+    }
+}
+
+/// Decrypts everything read from the inner reader
+pub struct AraCryptReader<R> {
+    inner: R,
+    ara_crypt: AraCrypt,
+}
+
+impl<R: Read> AraCryptReader<R> {
+    pub fn new(inner: R, keys: [u32; 3]) -> AraCryptReader<R> {
+        AraCryptReader {
+            inner,
+            ara_crypt: AraCrypt::new(keys),
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for AraCryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.ara_crypt.apply_keystream(&mut buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Encrypts everything written to the inner writer
+pub struct AraCryptWriter<W> {
+    inner: W,
+    ara_crypt: AraCrypt,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> AraCryptWriter<W> {
+    pub fn new(inner: W, keys: [u32; 3]) -> AraCryptWriter<W> {
+        AraCryptWriter {
+            inner,
+            ara_crypt: AraCrypt::new(keys),
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for AraCryptWriter<W> {
+    /// Consumes the whole buf, as the keystream can't be rewound after a short write
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.buf.clear();
+        self.buf.extend_from_slice(buf);
+        self.ara_crypt.apply_keystream(&mut self.buf);
+        self.inner.write_all(&self.buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
 }
 
 #[cfg(test)]
@@ -79,7 +171,7 @@ mod tests {
 
     #[test]
     fn test_aracrypt() {
-        let mut ara_crypt = AraCrypt::new([0x30313233, 0x34353637, 0x38393031]);
+        let mut ara_crypt = AraCrypt::new(MAP_HEADER_KEY);
 
         assert_eq!(ara_crypt.next(), 1);
         assert_eq!(ara_crypt.next(), 250);
@@ -90,4 +182,29 @@ mod tests {
         assert_eq!(ara_crypt.next(), 224);
         assert_eq!(ara_crypt.next(), 64);
     }
+
+    #[test]
+    fn fill_keystream() {
+        let mut keystream = [0; 8];
+        AraCrypt::new(MAP_HEADER_KEY).fill_keystream(&mut keystream);
+
+        assert_eq!(keystream, [1, 250, 56, 38, 228, 192, 224, 64]);
+    }
+
+    #[test]
+    fn reader_decrypts_writer() {
+        let plain: Vec<u8> = (0..100).collect();
+
+        let mut writer = AraCryptWriter::new(Vec::new(), MAP_HEADER_KEY);
+        writer.write_all(&plain[..30]).unwrap();
+        writer.write_all(&plain[30..]).unwrap();
+        let encrypted = writer.into_inner();
+        assert_eq!(&encrypted[..2], &[1, 1 ^ 250]);
+
+        let mut reader = AraCryptReader::new(&encrypted[..], MAP_HEADER_KEY);
+        let mut decrypted = [0; 100];
+        reader.read_exact(&mut decrypted[..7]).unwrap();
+        reader.read_exact(&mut decrypted[7..]).unwrap();
+        assert_eq!(decrypted[..], plain[..]);
+    }
 }
@@ -1,132 +1,303 @@
-use std::ffi::OsString;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::fs::OpenOptions;
 use std::io;
-use std::io::Read;
-use std::io::{BufReader, Seek, SeekFrom};
+use std::io::{BufReader, Seek};
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
 
-use byteorder::{ReadBytesExt, LE};
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 
+use crate::io::checksum::checksum;
+use crate::io::compress::compress;
+use crate::io::decompress::decompress_slice_exact;
+use crate::io::windows1252;
+
+/// Entry of an archive
 #[derive(Debug)]
-struct FileDescriptor {
+pub struct FileDescriptor {
     offset: u32,
     /// Length in bytes
     size: u32,
     /// Length in bytes after decompression
     size_decrypt: u32,
-    /// Filesystem path to file
+    /// Directory as stored, usually with backslashes
+    dir: String,
+    /// File name as stored
+    name: String,
+    /// Path with the original casing and `/` separators
     path: PathBuf,
+    /// Lookup key, see `normalize`
+    key: String,
+    /// Position of dir in the path list
+    path_index: u16,
     /// Wether the blob is compressed
     compressed: bool,
+    /// Field holding the compressed flag as stored
+    compression: u32,
+    /// Checksum of the stored bytes
+    checksum: u32,
+    /// Follows the path index, meaning unknown
+    unknown: u16,
+}
+
+impl FileDescriptor {
+    /// Path inside the archive with the original casing
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Directory as stored in the archive, e.g. `gfx\\tiles`
+    pub fn dir(&self) -> &str {
+        &self.dir
+    }
+    /// File name as stored in the archive
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Length in bytes as stored
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+    /// Length in bytes after decompression
+    pub fn size_decrypt(&self) -> u32 {
+        self.size_decrypt
+    }
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+    /// Checksum of the stored bytes as stored, the checksum of map segments,
+    /// see `io::checksum`
+    pub fn checksum(&self) -> u32 {
+        self.checksum
+    }
+    /// Field of unknown meaning after the path index, kept for repacking
+    pub fn unknown(&self) -> u16 {
+        self.unknown
+    }
+}
+
+/// Child of a directory inside an archive
+#[derive(Debug)]
+pub enum DirEntry<'a> {
+    Dir(PathBuf),
+    File(&'a FileDescriptor),
+}
+
+impl DirEntry<'_> {
+    pub fn path(&self) -> &Path {
+        match self {
+            DirEntry::Dir(path) => path,
+            DirEntry::File(file) => file.path(),
+        }
+    }
+}
+
+trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// Where the bytes of entries are read from
+enum Source {
+    File(File),
+    Reader(Mutex<Box<dyn ReadSeek>>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl std::fmt::Debug for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::File(file) => f.debug_tuple("File").field(file).finish(),
+            Source::Reader(_) => f.write_str("Reader"),
+            #[cfg(feature = "mmap")]
+            Source::Mapped(map) => f.debug_tuple("Mapped").field(map).finish(),
+        }
+    }
+}
+
+/// Header fields that are not derived from the entries, kept for repacking
+#[derive(Debug)]
+struct Header {
+    /// Position of the header in the file, everything in front is entry data
+    offset: u32,
+    /// Length of the header as stored
+    length: u32,
+    /// Always 4096 in the game files, maybe a read only flag
+    unknown: u32,
+    /// Path list as stored, entries refer to it by index
+    dirs: Vec<String>,
 }
 
 /// Archive manages access, and loading of graphical assets
 /// from the given installation root of the game.
+///
+/// The file stays open and entries are read with positional reads,
+/// so a shared `Archive` can be read from several threads at once.
+/// Names are decoded as Windows-1252.
 #[derive(Debug)]
 pub struct Archive {
-    path: PathBuf,
+    /// Location of the .lib, if read from a file
+    path: Option<PathBuf>,
+    source: Source,
+    header: Header,
     archive: Vec<FileDescriptor>,
+    /// Position in `archive` by normalized path, the first entry wins for duplicates
+    index: HashMap<String, usize>,
 }
 
 impl Archive {
     pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).open(&path)?;
+        let (header, archive, index) = read_index(BufReader::new(&file))?;
 
+        Ok(Archive {
+            path: Some(PathBuf::from(path.as_ref())),
+            source: Source::File(file),
+            header,
+            archive,
+            index,
+        })
+    }
+
+    /// Read an archive from any source, e.g. a .lib nested in another file.
+    /// Reads from several threads take turns seeking inner.
+    pub fn from_reader<R: Read + Seek + Send + 'static>(mut inner: R) -> io::Result<Self> {
+        let (header, archive, index) = read_index(&mut inner)?;
+
+        Ok(Archive {
+            path: None,
+            source: Source::Reader(Mutex::new(Box::new(inner))),
+            header,
+            archive,
+            index,
+        })
+    }
+
+    /// Map the file into memory instead of reading entries on demand.
+    /// `open_borrowed` then returns uncompressed entries without copying them.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the archive is alive,
+    /// by this or any other process. Entries borrowed from the mapping would
+    /// change under the reader, and reading a truncated part is undefined
+    /// behavior. Nothing here can prevent that, the caller has to ensure it,
+    /// e.g. by mapping only files of a read-only game installation.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn map<P: AsRef<Path>>(path: P) -> io::Result<Self> {
         let file = OpenOptions::new().read(true).open(&path)?;
+        // SAFETY: the caller guarantees the file is left alone while mapped
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        let (header, archive, index) = read_index(io::Cursor::new(&map[..]))?;
 
-        let mut reader = BufReader::<File>::new(file);
-
-        reader.seek(io::SeekFrom::End(-4))?;
-        let header_offset = reader.read_u32::<LE>()?; // 22828737 (Letztes Offset bei: 22824781)
-        
-        reader.seek(io::SeekFrom::Start(header_offset as u64))?;
-
-        let _length = reader.read_u32::<LE>()?; // 8380
-        let _unknown = reader.read_u32::<LE>()?; // 4096 (Readonly flag?)
-        let path_list_length = reader.read_u32::<LE>()?;
-        let path_count = reader.read_u32::<LE>()? as usize;
-        let file_list_length = reader.read_u32::<LE>()?;
-        let file_count = reader.read_u32::<LE>()? as usize;
-
-        // path name list [path name list length]
-        let mut buf = vec![0; path_list_length as usize];
-        reader.read_exact(&mut buf)?;
-        let path_names = String::from_utf8(buf).expect("valid utf-8 string");
-        let paths: Vec<PathBuf> = path_names
-            .split_terminator('\0')
-            .map(PathBuf::from)
-            .collect();
-        if paths.len() != path_count {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid path data"));
-        }
+        Ok(Archive {
+            path: Some(PathBuf::from(path.as_ref())),
+            source: Source::Mapped(map),
+            header,
+            archive,
+            index,
+        })
+    }
 
-        // file name list [file name list length]
-        let mut buf = vec![0; file_list_length as usize];
-        reader.read_exact(&mut buf)?;
-        let file_names = String::from_utf8(buf).expect("valid utf-8 string");
-        let files: Vec<OsString> = file_names
-            .split_terminator('\0')
-            .map(|str| OsString::from_str(str).unwrap())
-            .collect();
-        if files.len() != file_count {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid file data"));
-        }
-
-        let mut archive = Vec::new();
-
-        files.iter().try_for_each(|name| -> io::Result<()> {
-
-            let offset = reader.read_u32::<LE>()?;
-            let size = reader.read_u32::<LE>()?;
-            let size_decrypt = reader.read_u32::<LE>()?;
-            let path_index = reader.read_u16::<LE>()? as usize;
-            reader.seek_relative(2)?; // skip unknown
-            let compressed = reader.read_u32::<LE>()? == 1;
-            
-            let base_path = paths.get(path_index).unwrap();
-            let path = base_path.join(name.clone());
-
-            let virtual_file = FileDescriptor {
-                offset,
-                size,
-                size_decrypt,
-                path,
-                compressed,                
-            };
+    /// Location of the .lib file, `None` for archives read with `from_reader`
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
 
-            let _checksum = reader.read_u32::<LE>()?;
+    /// Header length as stored
+    pub fn header_length(&self) -> u32 {
+        self.header.length
+    }
 
-            archive.push(virtual_file);
+    /// Header field of unknown meaning, 4096 in the game files
+    pub fn unknown(&self) -> u32 {
+        self.header.unknown
+    }
 
-            Ok(())
-        })?;
+    /// All entries in the order they are stored
+    pub fn entries(&self) -> impl Iterator<Item = &FileDescriptor> {
+        self.archive.iter()
+    }
 
-        let loader = Archive {
-            path: PathBuf::from(path.as_ref()),
-            archive,
-        };
+    /// Paths are matched case-insensitively and may use either separator,
+    /// so `gfx\\5.gfx` finds `GFX/5.GFX`.
+    pub fn metadata<P: AsRef<Path>>(&self, path: P) -> Option<&FileDescriptor> {
+        let key = normalize(&path.as_ref().to_string_lossy());
+        self.index.get(&key).map(|i| &self.archive[*i])
+    }
+
+    /// Files and directories directly inside dir, use "" for the root.
+    /// Fails with `NotFound` if dir holds no entries.
+    pub fn read_dir<P: AsRef<Path>>(&self, dir: P) -> io::Result<Vec<DirEntry<'_>>> {
+        let dir = normalize(&dir.as_ref().to_string_lossy());
+        let depth = split_path(&dir).count();
+        let mut entries = Vec::new();
+        let mut dirs = HashSet::new();
+
+        for file in &self.archive {
+            let rest = if dir.is_empty() {
+                Some(file.key.as_str())
+            } else {
+                file.key
+                    .strip_prefix(dir.as_str())
+                    .and_then(|rest| rest.strip_prefix('/'))
+            };
+            let Some(rest) = rest else {
+                continue;
+            };
+            match rest.split_once('/') {
+                None => entries.push(DirEntry::File(file)),
+                Some((child, _)) => {
+                    if dirs.insert(child) {
+                        // keep the casing of the first entry inside
+                        let child = file.path.components().take(depth + 1).collect();
+                        entries.push(DirEntry::Dir(child));
+                    }
+                }
+            }
+        }
+
+        if entries.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no directory {} in archive", dir),
+            ));
+        }
+        Ok(entries)
+    }
 
-        Ok(loader)
+    /// Entries whose path matches pattern, e.g. "gfx/*.gfx", ignoring case.
+    /// `*` and `?` match within a path component, `**` matches any number of components.
+    pub fn glob(&self, pattern: &str) -> impl Iterator<Item = &FileDescriptor> {
+        let pattern = normalize(pattern);
+        self.archive.iter().filter(move |file| {
+            let pattern: Vec<&str> = split_path(&pattern).collect();
+            let components: Vec<&str> = split_path(&file.key).collect();
+            glob_path(&pattern, &components)
+        })
     }
 
     pub fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<Option<Vec<u8>>> {
-        let virtual_file = self.archive.iter().find(|f| f.path == path.as_ref());
+        Ok(self.open_borrowed(path)?.map(Cow::into_owned))
+    }
 
-        match virtual_file {
+    /// Like `open`, but uncompressed entries of a mapped archive are borrowed
+    /// instead of copied.
+    pub fn open_borrowed<P: AsRef<Path>>(&self, path: P) -> io::Result<Option<Cow<'_, [u8]>>> {
+        match self.metadata(path) {
             None => Ok(None),
             Some(f) => {
-                let file = OpenOptions::new().read(true).open(&self.path)?;
-                let mut reader = BufReader::<File>::new(file);
-        
-                reader.seek(SeekFrom::Start(f.offset as u64))?;
-        
-                let mut buf = vec![0;f.size as usize];
-        
-                reader.read_exact(&mut buf)?;
-        
+                let buf = self.read_stored(f)?;
+
                 if f.compressed {
-                    todo!("decompression not implemented");
+                    // also fails unless exactly size_decrypt bytes come out
+                    let decrypt =
+                        decompress_slice_exact(&buf, f.size_decrypt as usize).map_err(|e| {
+                            io::Error::new(e.kind(), format!("{}: {}", f.path.display(), e))
+                        })?;
+                    return Ok(Some(Cow::Owned(decrypt)));
                 }
 
                 Ok(Some(buf))
@@ -135,22 +306,875 @@ impl Archive {
     }
 }
 
+impl Archive {
+    /// Check the stored bytes of every entry against its checksum.
+    ///
+    /// Entries are bounds checked when the archive is opened, `Truncated` means
+    /// the file shrank since.
+    pub fn verify(&self) -> io::Result<Vec<(&FileDescriptor, Corruption)>> {
+        let len = match &self.source {
+            Source::File(file) => file.metadata()?.len(),
+            Source::Reader(inner) => inner
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .seek(io::SeekFrom::End(0))?,
+            #[cfg(feature = "mmap")]
+            Source::Mapped(map) => map.len() as u64,
+        };
+        let mut corrupted = Vec::new();
+
+        for f in &self.archive {
+            if f.offset as u64 + f.size as u64 > len {
+                corrupted.push((f, Corruption::Truncated));
+                continue;
+            }
+
+            let actual = checksum(&self.read_stored(f)?);
+            if actual != f.checksum {
+                let expected = f.checksum;
+                corrupted.push((f, Corruption::Checksum { expected, actual }));
+            }
+        }
+
+        Ok(corrupted)
+    }
+
+    /// Write the archive again, keeping the stored bytes and all header fields.
+    ///
+    /// Everything in front of the header is copied as stored, so entries keep
+    /// their offsets and gaps between them survive. The header is written from
+    /// the parsed fields, so an unmodified archive comes out byte-identical
+    /// unless its name lists lack the final NUL or bytes follow the last record.
+    pub fn repack<W: Write>(&self, inner: W) -> io::Result<W> {
+        let mut builder = ArchiveBuilder::new(inner);
+        builder.dirs = self.header.dirs.clone();
+        builder.length = Some(self.header.length);
+        builder.unknown = self.header.unknown;
+
+        let end = self.header.offset as u64;
+        while builder.position < end {
+            let len = (end - builder.position).min(COPY_CHUNK as u64) as usize;
+            let bytes = self.read_range(builder.position, len)?;
+            builder.inner.write_all(&bytes)?;
+            builder.position += len as u64;
+        }
+
+        for f in &self.archive {
+            builder.names.push(encode_name(&f.name)?);
+            builder.records.push(Record {
+                offset: f.offset,
+                size: f.size,
+                size_decrypt: f.size_decrypt,
+                path_index: f.path_index,
+                unknown: f.unknown,
+                compression: f.compression,
+                checksum: f.checksum,
+            });
+        }
+
+        builder.finish()
+    }
+
+    /// Bytes of an entry as stored in the file
+    fn read_stored(&self, f: &FileDescriptor) -> io::Result<Cow<'_, [u8]>> {
+        self.read_range(f.offset as u64, f.size as usize)
+    }
+
+    fn read_range(&self, offset: u64, len: usize) -> io::Result<Cow<'_, [u8]>> {
+        match &self.source {
+            Source::File(file) => {
+                let mut buf = vec![0; len];
+                read_exact_at(file, &mut buf, offset)?;
+                Ok(Cow::Owned(buf))
+            }
+            Source::Reader(inner) => {
+                let mut inner = inner.lock().unwrap_or_else(PoisonError::into_inner);
+                let mut buf = vec![0; len];
+                inner.seek(io::SeekFrom::Start(offset))?;
+                inner.read_exact(&mut buf)?;
+                Ok(Cow::Owned(buf))
+            }
+            #[cfg(feature = "mmap")]
+            Source::Mapped(map) => {
+                let start = offset as usize;
+                map.get(start..start + len)
+                    .map(Cow::Borrowed)
+                    .ok_or_else(|| io::ErrorKind::UnexpectedEof.into())
+            }
+        }
+    }
+}
+
+/// Size of the chunks `repack` copies entry data in
+const COPY_CHUNK: usize = 0x10000;
+
+/// Header, descriptors and lookup index of a .lib
+fn read_index<R: Read + Seek>(
+    mut reader: R,
+) -> io::Result<(Header, Vec<FileDescriptor>, HashMap<String, usize>)> {
+    let len = reader.seek(io::SeekFrom::End(-4))? + 4;
+    let header_offset = reader.read_u32::<LE>()?; // 22828737 (Letztes Offset bei: 22824781)
+
+    reader.seek(io::SeekFrom::Start(header_offset as u64))?;
+
+    let length = reader.read_u32::<LE>()?; // 8380
+    let unknown = reader.read_u32::<LE>()?; // 4096 (Readonly flag?)
+    let path_list_length = reader.read_u32::<LE>()?;
+    let path_count = reader.read_u32::<LE>()? as usize;
+    let file_list_length = reader.read_u32::<LE>()?;
+    let file_count = reader.read_u32::<LE>()? as usize;
+
+    // lengths are checked before allocating for them
+    if header_offset as u64 + 24 + path_list_length as u64 + file_list_length as u64 > len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "name lists exceed the archive",
+        ));
+    }
+
+    // path name list [path name list length]
+    let mut buf = vec![0; path_list_length as usize];
+    reader.read_exact(&mut buf)?;
+    let paths = name_table(&buf);
+    if paths.len() != path_count {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid path data",
+        ));
+    }
+
+    // file name list [file name list length]
+    let mut buf = vec![0; file_list_length as usize];
+    reader.read_exact(&mut buf)?;
+    let files = name_table(&buf);
+    if files.len() != file_count {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid file data",
+        ));
+    }
+
+    let mut archive = Vec::new();
+
+    files.iter().try_for_each(|name| -> io::Result<()> {
+        let offset = reader.read_u32::<LE>()?;
+        let size = reader.read_u32::<LE>()?;
+        let size_decrypt = reader.read_u32::<LE>()?;
+        let path_index = reader.read_u16::<LE>()?;
+        let unknown = reader.read_u16::<LE>()?;
+        let compression = reader.read_u32::<LE>()?;
+        let checksum = reader.read_u32::<LE>()?;
+
+        if offset as u64 + size as u64 > len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} exceeds the archive", name),
+            ));
+        }
+
+        let dir = paths
+            .get(path_index as usize)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid path index"))?;
+        let path: PathBuf = split_path(dir).chain([name.as_str()]).collect();
+
+        let virtual_file = FileDescriptor {
+            offset,
+            size,
+            size_decrypt,
+            dir: dir.to_string(),
+            name: name.to_string(),
+            key: normalize(&path.to_string_lossy()),
+            path,
+            path_index,
+            compressed: compression == 1,
+            compression,
+            checksum,
+            unknown,
+        };
+
+        archive.push(virtual_file);
+
+        Ok(())
+    })?;
+
+    let mut index = HashMap::with_capacity(archive.len());
+    for (i, virtual_file) in archive.iter().enumerate() {
+        index.entry(virtual_file.key.clone()).or_insert(i);
+    }
+
+    let header = Header {
+        offset: header_offset,
+        length,
+        unknown,
+        dirs: paths,
+    };
+    Ok((header, archive, index))
+}
+
+/// Damage found by `Archive::verify`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corruption {
+    /// Checksum of the stored bytes differs from the descriptor
+    Checksum { expected: u32, actual: u32 },
+    /// Entry extends past the end of the file, which shrank after opening
+    Truncated,
+}
+
+/// Descriptor record as written by `ArchiveBuilder`
+struct Record {
+    offset: u32,
+    size: u32,
+    size_decrypt: u32,
+    path_index: u16,
+    unknown: u16,
+    compression: u32,
+    checksum: u32,
+}
+
+/// Writes a .lib readable by `Archive`.
+///
+/// File contents are written as they are added, the path and file name tables
+/// and the descriptor records follow in `finish`.
+pub struct ArchiveBuilder<W: Write> {
+    inner: W,
+    /// Bytes written so far
+    position: u64,
+    dirs: Vec<String>,
+    /// Windows-1252 encoded file names
+    names: Vec<Vec<u8>>,
+    records: Vec<Record>,
+    /// Header length to store instead of the actual one
+    length: Option<u32>,
+    unknown: u32,
+}
+
+impl<W: Write> ArchiveBuilder<W> {
+    pub fn new(inner: W) -> ArchiveBuilder<W> {
+        ArchiveBuilder {
+            inner,
+            position: 0,
+            dirs: Vec::new(),
+            names: Vec::new(),
+            records: Vec::new(),
+            length: None,
+            unknown: 4096,
+        }
+    }
+
+    /// Add a file at path, e.g. `gfx/5.gfx`, optionally compressing it.
+    /// Directories are stored with backslashes like the game does.
+    pub fn add(&mut self, path: &str, data: &[u8], compressed: bool) -> io::Result<()> {
+        let components: Vec<&str> = split_path(path).collect();
+        let Some((name, dir)) = components.split_last() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "empty path in archive",
+            ));
+        };
+
+        let stored = if compressed {
+            compress(data)?
+        } else {
+            data.to_vec()
+        };
+        self.add_raw(
+            &dir.join("\\"),
+            name,
+            &stored,
+            format_u32(data.len())?,
+            compressed,
+        )
+    }
+
+    /// Add stored bytes as they are, e.g. when repacking entries of another archive.
+    /// Fails with `InvalidInput` if dir or name can't be written as Windows-1252.
+    pub fn add_raw(
+        &mut self,
+        dir: &str,
+        name: &str,
+        stored: &[u8],
+        size_decrypt: u32,
+        compressed: bool,
+    ) -> io::Result<()> {
+        let path_index = match self.dirs.iter().position(|d| d == dir) {
+            Some(i) => i,
+            None => {
+                encode_name(dir)?;
+                self.dirs.push(dir.to_string());
+                self.dirs.len() - 1
+            }
+        };
+        let Ok(path_index) = u16::try_from(path_index) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "archive exceeds the format limits",
+            ));
+        };
+
+        let record = Record {
+            offset: 0,
+            size: 0,
+            size_decrypt,
+            path_index,
+            unknown: 0,
+            compression: compressed as u32,
+            checksum: checksum(stored),
+        };
+        self.push(name, stored, record)
+    }
+
+    /// Write stored and keep record with its offset and size filled in
+    fn push(&mut self, name: &str, stored: &[u8], record: Record) -> io::Result<()> {
+        let offset = format_u32(self.position)?;
+        let size = format_u32(stored.len())?;
+        let name = encode_name(name)?;
+
+        self.inner.write_all(stored)?;
+        self.position += stored.len() as u64;
+
+        self.names.push(name);
+        self.records.push(Record {
+            offset,
+            size,
+            ..record
+        });
+
+        Ok(())
+    }
+
+    /// Write the header and return inner
+    pub fn finish(mut self) -> io::Result<W> {
+        let header_offset = format_u32(self.position)?;
+
+        let dirs = self
+            .dirs
+            .iter()
+            .map(|dir| encode_name(dir))
+            .collect::<io::Result<Vec<_>>>()?;
+        let path_list = name_list(&dirs);
+        let file_list = name_list(&self.names);
+
+        // repacking keeps the stored length
+        let length = match self.length {
+            Some(length) => length,
+            None => header_length(&path_list, &file_list, self.records.len())?,
+        };
+
+        let writer = &mut self.inner;
+        writer.write_u32::<LE>(length)?;
+        writer.write_u32::<LE>(self.unknown)?;
+        writer.write_u32::<LE>(format_u32(path_list.len())?)?;
+        writer.write_u32::<LE>(format_u32(self.dirs.len())?)?;
+        writer.write_u32::<LE>(format_u32(file_list.len())?)?;
+        writer.write_u32::<LE>(format_u32(self.names.len())?)?;
+        writer.write_all(&path_list)?;
+        writer.write_all(&file_list)?;
+
+        for record in &self.records {
+            writer.write_u32::<LE>(record.offset)?;
+            writer.write_u32::<LE>(record.size)?;
+            writer.write_u32::<LE>(record.size_decrypt)?;
+            writer.write_u16::<LE>(record.path_index)?;
+            writer.write_u16::<LE>(record.unknown)?;
+            writer.write_u32::<LE>(record.compression)?;
+            writer.write_u32::<LE>(record.checksum)?;
+        }
+
+        writer.write_u32::<LE>(header_offset)?;
+        writer.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+/// Length or count as stored, `InvalidInput` if it exceeds the 32 bit fields
+fn format_u32<T: TryInto<u32>>(value: T) -> io::Result<u32> {
+    value.try_into().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "archive exceeds the format limits",
+        )
+    })
+}
+
+/// Header up to the trailing offset: the fields, both name lists and the records
+fn header_length(path_list: &[u8], file_list: &[u8], records: usize) -> io::Result<u32> {
+    format_u32(24 + path_list.len() as u64 + file_list.len() as u64 + 24 * records as u64)
+}
+
+/// NUL terminated names
+fn name_list(names: &[Vec<u8>]) -> Vec<u8> {
+    let mut list = Vec::new();
+    for name in names {
+        list.extend_from_slice(name);
+        list.push(0);
+    }
+    list
+}
+
+/// Names of a NUL terminated list, see `name_list`
+fn name_table(list: &[u8]) -> Vec<String> {
+    if list.is_empty() {
+        return Vec::new();
+    }
+    let list = list.strip_suffix(&[0]).unwrap_or(list);
+    list.split(|&byte| byte == 0)
+        .map(windows1252::decode)
+        .collect()
+}
+
+fn encode_name(name: &str) -> io::Result<Vec<u8>> {
+    windows1252::encode(name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} can't be written as Windows-1252", name),
+        )
+    })
+}
+
+/// Components of a path using either separator
+pub(crate) fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split(['/', '\\'])
+        .filter(|component| !component.is_empty())
+}
+
+/// Lookup key of a path: lowercase components joined by `/`
+pub(crate) fn normalize(path: &str) -> String {
+    split_path(path)
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn glob_path(pattern: &[&str], components: &[&str]) -> bool {
+    match (pattern.split_first(), components.split_first()) {
+        (None, None) => true,
+        (Some((&"**", rest)), _) => {
+            glob_path(rest, components)
+                || (!components.is_empty() && glob_path(pattern, &components[1..]))
+        }
+        (Some((p, pattern)), Some((c, components))) => {
+            glob_name(p.as_bytes(), c.as_bytes()) && glob_path(pattern, components)
+        }
+        _ => false,
+    }
+}
+
+/// Match a single path component against `*` and `?` wildcards
+fn glob_name(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            glob_name(rest, name) || (!name.is_empty() && glob_name(pattern, &name[1..]))
+        }
+        (Some((b'?', pattern)), Some((_, name))) => glob_name(pattern, name),
+        (Some((p, pattern)), Some((c, name))) => p == c && glob_name(pattern, name),
+        _ => false,
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::io::temp_dir::TempDir;
 
     #[test]
     fn initialize() {
         // TODO: Need a copyright free variant of all asset types
-        let install_root: PathBuf = [
-            "data",
-            "Settlers 4 Gold",
-            "gfx.lib"
-        ]
-        .iter()
-        .collect();
-        let gfx_loader = Archive::new(install_root);
-        assert!(gfx_loader.is_ok());
+        let install_root: PathBuf = ["data", "Settlers 4 Gold", "gfx.lib"].iter().collect();
+        let archive = Archive::new(install_root).unwrap();
+
+        // the length ArchiveBuilder writes for the same lists
+        let dirs: Vec<_> = archive
+            .header
+            .dirs
+            .iter()
+            .map(|d| encode_name(d).unwrap())
+            .collect();
+        let names: Vec<_> = archive
+            .entries()
+            .map(|f| encode_name(f.name()).unwrap())
+            .collect();
+        let length = header_length(&name_list(&dirs), &name_list(&names), names.len());
+        assert_eq!(archive.header_length(), length.unwrap());
+
+        assert!(archive.verify().unwrap().is_empty());
+    }
+
+    /// Entry as (directory, file name, stored bytes, decompressed size, compressed)
+    type Entry<'a> = (&'a str, &'a str, Vec<u8>, usize, bool);
+
+    /// Write the entries to inner as a .lib
+    fn write_entries<W: Write>(inner: W, entries: &[Entry]) -> W {
+        let mut builder = ArchiveBuilder::new(inner);
+        for (dir, name, data, size_decrypt, compressed) in entries {
+            builder
+                .add_raw(dir, name, data, *size_decrypt as u32, *compressed)
+                .unwrap();
+        }
+        builder.finish().unwrap()
+    }
+
+    /// Archive of the entries in memory, for tests not about reading files
+    fn memory_archive(entries: &[Entry]) -> Archive {
+        let file = write_entries(Vec::new(), entries);
+        Archive::from_reader(io::Cursor::new(file)).unwrap()
+    }
+
+    /// Write a .lib to dir for the tests of reading from disk
+    fn write_archive(dir: &TempDir, entries: &[Entry]) -> PathBuf {
+        let path = dir.join("test.lib");
+        write_entries(File::create(&path).unwrap(), entries);
+        path
+    }
+
+    #[test]
+    fn build_archive() {
+        let data = b"Die Siedler IV ".repeat(100);
+
+        let mut builder = ArchiveBuilder::new(Vec::new());
+        builder.add("gfx/0.gfx", &data, true).unwrap();
+        builder.add("gfx\\0.gil", &data, false).unwrap();
+        builder.add("pal/0.p46", b"palette", false).unwrap();
+        assert!(builder.add("/", b"", false).is_err());
+        let file = builder.finish().unwrap();
+
+        // body, then the header at the offset stored in the last 4 bytes
+        let header_offset = u32::from_le_bytes(file[file.len() - 4..].try_into().unwrap());
+        let header = &file[header_offset as usize..file.len() - 4];
+        assert_eq!(
+            u32::from_le_bytes(header[0..4].try_into().unwrap()),
+            header.len() as u32
+        );
+        assert_eq!(&header[24..32], b"gfx\0pal\0");
+        assert_eq!(&header[32..38], b"0.gfx\0");
+
+        let archive = Archive::from_reader(io::Cursor::new(file)).unwrap();
+
+        let gfx = archive.metadata("gfx/0.gfx").unwrap();
+        assert!(gfx.is_compressed());
+        assert!(gfx.size() < data.len() as u32);
+        assert_eq!(gfx.dir(), "gfx");
+        assert_eq!(archive.open("gfx/0.gfx").unwrap(), Some(data.clone()));
+        assert_eq!(archive.open("gfx/0.gil").unwrap(), Some(data));
+        assert_eq!(
+            archive.open("pal/0.p46").unwrap(),
+            Some(b"palette".to_vec())
+        );
+    }
+
+    #[test]
+    fn format_limits() {
+        assert_eq!(format_u32(u32::MAX as u64).unwrap(), u32::MAX);
+        let e = format_u32(u32::MAX as u64 + 1).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn repack_is_lossless() {
+        let data = b"Die Siedler IV ".repeat(100);
+
+        let mut builder = ArchiveBuilder::new(Vec::new());
+        builder.add("gfx/0.gfx", &data, true).unwrap();
+        builder.add("Straße €.txt", b"text", false).unwrap();
+        assert!(builder.add("gfx/\u{0100}.gfx", b"", false).is_err());
+        let mut file = builder.finish().unwrap();
+        assert!(file.windows(9).any(|w| w == b"Stra\xDFe \x80."));
+
+        // unusual values in every field the builder doesn't pick
+        let header_offset = u32::from_le_bytes(file[file.len() - 4..].try_into().unwrap());
+        let header = header_offset as usize;
+        let lists = u32::from_le_bytes(file[header + 8..header + 12].try_into().unwrap())
+            + u32::from_le_bytes(file[header + 16..header + 20].try_into().unwrap());
+        let records = header + 24 + lists as usize;
+        file[header..header + 4].copy_from_slice(&9999u32.to_le_bytes());
+        file[header + 4..header + 8].copy_from_slice(&1234u32.to_le_bytes());
+        file[records + 14..records + 16].copy_from_slice(&7u16.to_le_bytes());
+        file[records + 24 + 16..records + 24 + 20].copy_from_slice(&2u32.to_le_bytes());
+
+        let archive = Archive::from_reader(io::Cursor::new(file.clone())).unwrap();
+        assert_eq!(archive.path(), None);
+        assert_eq!(archive.header_length(), 9999);
+        assert_eq!(archive.unknown(), 1234);
+        assert_eq!(archive.metadata("gfx/0.gfx").unwrap().unknown(), 7);
+        assert_eq!(archive.open("gfx/0.gfx").unwrap(), Some(data));
+
+        let text = archive.metadata("straße €.TXT").unwrap();
+        assert_eq!(text.name(), "Straße €.txt");
+        assert!(!text.is_compressed());
+        assert_eq!(
+            archive.open("straße €.txt").unwrap(),
+            Some(b"text".to_vec())
+        );
+
+        assert_eq!(archive.repack(Vec::new()).unwrap(), file);
+    }
+
+    #[test]
+    fn repack_keeps_offsets() {
+        // padding in front of and between entries, and one entry stored twice
+        let mut builder = ArchiveBuilder::new(Vec::new());
+        builder.inner.extend_from_slice(b"pad");
+        builder.position += 3;
+        builder.add("gfx/0.gfx", b"first", false).unwrap();
+        builder.inner.extend_from_slice(&[0; 5]);
+        builder.position += 5;
+        builder.add("gfx/1.gfx", b"second", false).unwrap();
+        builder.names.push(b"2.gfx".to_vec());
+        builder.records.push(Record {
+            offset: 3,
+            size: 5,
+            size_decrypt: 5,
+            path_index: 0,
+            unknown: 0,
+            compression: 0,
+            checksum: checksum(b"first"),
+        });
+        let file = builder.finish().unwrap();
+
+        let archive = Archive::from_reader(io::Cursor::new(file.clone())).unwrap();
+        assert_eq!(archive.open("gfx/1.gfx").unwrap(), Some(b"second".to_vec()));
+        assert_eq!(archive.open("gfx/2.gfx").unwrap(), Some(b"first".to_vec()));
+        assert_eq!(archive.repack(Vec::new()).unwrap(), file);
+    }
+
+    #[test]
+    fn open_compressed_entry() {
+        let data = b"Die Siedler IV ".repeat(100);
+        let compressed = compress(&data).unwrap();
+        let dir = TempDir::new("open_compressed_entry");
+        let path = write_archive(
+            &dir,
+            &[
+                ("gfx", "0.gfx", compressed, data.len(), true),
+                ("gfx", "0.gil", data.clone(), data.len(), false),
+            ],
+        );
+
+        let archive = Archive::new(&path).unwrap();
+        assert_eq!(archive.open("gfx/0.gfx").unwrap(), Some(data.clone()));
+        assert_eq!(archive.open("gfx/0.gil").unwrap(), Some(data));
+        assert_eq!(archive.open("gfx/1.gfx").unwrap(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn mapped_archive() {
+        let data = b"Die Siedler IV ".repeat(100);
+        let compressed = compress(&data).unwrap();
+        let dir = TempDir::new("mapped_archive");
+        let path = write_archive(
+            &dir,
+            &[
+                ("gfx", "0.gfx", compressed, data.len(), true),
+                ("gfx", "0.gil", data.clone(), data.len(), false),
+            ],
+        );
+
+        // SAFETY: the file is private to this test and removed after the drop
+        let archive = unsafe { Archive::map(&path) }.unwrap();
+        assert!(matches!(
+            archive.open_borrowed("gfx/0.gil").unwrap(),
+            Some(Cow::Borrowed(bytes)) if bytes == &data[..]
+        ));
+        assert!(matches!(
+            archive.open_borrowed("gfx/0.gfx").unwrap(),
+            Some(Cow::Owned(bytes)) if bytes == data
+        ));
+        assert_eq!(archive.open("gfx/0.gfx").unwrap(), Some(data));
+        assert_eq!(archive.open_borrowed("gfx/1.gfx").unwrap(), None);
+        assert!(archive.verify().unwrap().is_empty());
+    }
+
+    #[test]
+    fn browse_entries() {
+        let archive = memory_archive(&[
+            ("gfx", "0.gfx", vec![1; 10], 10, false),
+            ("gfx", "0.gil", vec![2; 20], 20, false),
+            ("gfx/tiles", "0.gfx", vec![3; 30], 30, false),
+            ("snd", "0.snd", vec![4; 40], 40, false),
+        ]);
+
+        let sizes: Vec<u32> = archive.entries().map(|file| file.size()).collect();
+        assert_eq!(sizes, [10, 20, 30, 40]);
+
+        let file = archive.metadata("gfx/0.gil").unwrap();
+        assert_eq!(file.path(), Path::new("gfx/0.gil"));
+        assert_eq!((file.size_decrypt(), file.is_compressed()), (20, false));
+        assert!(archive.metadata("gfx/1.gil").is_none());
+
+        let paths = |entries: Vec<DirEntry>| -> Vec<PathBuf> {
+            entries.iter().map(|e| e.path().to_path_buf()).collect()
+        };
+        let root = archive.read_dir("").unwrap();
+        assert!(matches!(root[..], [DirEntry::Dir(_), DirEntry::Dir(_)]));
+        assert_eq!(paths(root), [Path::new("gfx"), Path::new("snd")]);
+        assert_eq!(
+            paths(archive.read_dir("gfx").unwrap()),
+            [
+                Path::new("gfx/0.gfx"),
+                Path::new("gfx/0.gil"),
+                Path::new("gfx/tiles")
+            ]
+        );
+        let e = archive.read_dir("gfx/0.gfx").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+
+        let glob = |pattern| -> Vec<&Path> { archive.glob(pattern).map(|f| f.path()).collect() };
+        assert_eq!(glob("gfx/*.gfx"), [Path::new("gfx/0.gfx")]);
+        assert_eq!(glob("gfx/0.g?l"), [Path::new("gfx/0.gil")]);
+        assert_eq!(
+            glob("**/0.gfx"),
+            [Path::new("gfx/0.gfx"), Path::new("gfx/tiles/0.gfx")]
+        );
+        assert_eq!(glob("*/*").len(), 3);
+        assert!(glob("*.gfx").is_empty());
+    }
+
+    #[test]
+    fn windows_paths() {
+        let archive = memory_archive(&[
+            ("Gfx\\Tiles", "Grass.GFX", vec![1; 10], 10, false),
+            ("gfx", "5.gfx", vec![2; 20], 20, false),
+        ]);
+
+        for name in ["gfx\\5.gfx", "GFX/5.GFX", "/gfx//5.Gfx"] {
+            assert_eq!(archive.open(name).unwrap(), Some(vec![2; 20]), "{}", name);
+        }
+        let file = archive.metadata("gfx/tiles/grass.gfx").unwrap();
+        assert_eq!(file.path(), Path::new("Gfx/Tiles/Grass.GFX"));
+        assert_eq!((file.dir(), file.name()), ("Gfx\\Tiles", "Grass.GFX"));
+
+        let root: Vec<_> = archive.read_dir("").unwrap();
+        assert_eq!(root.len(), 1);
+        assert_eq!(root[0].path(), Path::new("Gfx"));
+        assert_eq!(archive.read_dir("GFX\\tiles").unwrap().len(), 1);
+        assert_eq!(archive.glob("gfx\\*\\*.gfx").count(), 1);
+    }
+
+    #[test]
+    fn verify_entries() {
+        let dir = TempDir::new("verify_entries");
+        let path = write_archive(
+            &dir,
+            &[
+                ("gfx", "0.gfx", vec![1; 10], 10, false),
+                ("gfx", "1.gfx", vec![2; 20], 20, false),
+                ("gfx", "2.gfx", vec![3; 30], 30, false),
+            ],
+        );
+        let archive = Archive::new(&path).unwrap();
+        assert!(archive.verify().unwrap().is_empty());
+        let expected = archive.metadata("gfx/1.gfx").unwrap().checksum();
+        assert_eq!(expected, checksum(&[2; 20]));
+
+        // an entry past the end of the file is rejected up front
+        let mut file = std::fs::read(&path).unwrap();
+        let header_offset = u32::from_le_bytes(file[file.len() - 4..].try_into().unwrap());
+        let record = header_offset as usize + 24 + "gfx\0".len() + "0.gfx\0".len() * 3 + 2 * 24;
+        let size = file[record + 4..record + 8].to_vec();
+        file[record + 4..record + 8].copy_from_slice(&1000_u32.to_le_bytes());
+        std::fs::write(&path, &file).unwrap();
+        let e = Archive::new(&path).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        // damage the body of 1.gfx, then cut 2.gfx short once the archive is open
+        file[record + 4..record + 8].copy_from_slice(&size);
+        file[15] ^= 0xFF;
+        std::fs::write(&path, file).unwrap();
+
+        let archive = Archive::new(&path).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(40)
+            .unwrap();
+        let corrupted: Vec<_> = archive
+            .verify()
+            .unwrap()
+            .into_iter()
+            .map(|(f, corruption)| (f.name(), corruption))
+            .collect();
+        let mut damaged = vec![2; 20];
+        damaged[5] ^= 0xFF;
+        let actual = checksum(&damaged);
+        assert_eq!(
+            corrupted,
+            [
+                ("1.gfx", Corruption::Checksum { expected, actual }),
+                ("2.gfx", Corruption::Truncated)
+            ]
+        );
+    }
+
+    #[test]
+    fn open_from_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Archive>();
+
+        let entries: Vec<(String, Vec<u8>)> = (0..64)
+            .map(|i| (format!("{}.gfx", i), vec![i as u8; 100 + i]))
+            .collect();
+        let dir = TempDir::new("open_from_threads");
+        let path = write_archive(
+            &dir,
+            &entries
+                .iter()
+                .map(|(name, data)| ("gfx", name.as_str(), data.clone(), data.len(), false))
+                .collect::<Vec<_>>(),
+        );
+
+        let archive = Archive::new(&path).unwrap();
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let (archive, entries) = (&archive, &entries);
+                scope.spawn(move || {
+                    for (name, data) in entries.iter().skip(thread) {
+                        let name = format!("gfx/{}", name);
+                        assert_eq!(archive.open(name).unwrap().as_ref(), Some(data));
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn open_corrupted_entry() {
+        let data = b"Die Siedler IV ".repeat(100);
+        let compressed = compress(&data).unwrap();
+        let archive = memory_archive(&[
+            ("gfx", "short.gfx", compressed.clone(), data.len() + 1, true),
+            ("gfx", "raw.gfx", data.clone(), data.len(), true),
+        ]);
+
+        for name in ["gfx/short.gfx", "gfx/raw.gfx"] {
+            let e = archive.open(name).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData, "{}", e);
+        }
     }
 }
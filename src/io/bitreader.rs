@@ -1,13 +1,14 @@
-#![allow(dead_code, unused)]
 use byteorder::ReadBytesExt;
 use std::io;
 use std::io::Read;
 
+/// Reads bits MSB first from inner, one byte at a time.
 #[derive(Debug, PartialEq)]
 pub struct BitReader<R> {
     inner: R,
     cached_bits_count: u8, // cached bits
-    cache: u16,            // store for loaded bits
+    cache: u64,            // store for loaded bits, next bit in the MSB
+    position: u64,         // bits consumed since the start of inner
 }
 
 impl<R: Read> BitReader<R> {
@@ -16,66 +17,265 @@ impl<R: Read> BitReader<R> {
             inner,
             cached_bits_count: 0,
             cache: 0,
+            position: 0,
         }
     }
 
     /// Bit offset from the byte at the starting position of inner.
     pub fn with_offset(offset: u8, inner: R) -> io::Result<BitReader<R>> {
         if offset > 8 {
-            return Err(io::Error::new(io::ErrorKind::InvalidInput, "offset bigger than 8 bits"));
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "offset bigger than 8 bits",
+            ));
         }
 
         let mut reader = BitReader::new(inner);
-
-        reader.read_u8(8);
-        reader.cached_bits_count = 8 - offset;
-        reader.cache = reader.cache << 8 + offset;
+        reader.skip_bits(offset as u64)?;
 
         Ok(reader)
     }
 
-    pub fn read_n_bits(&mut self, n: usize) -> io::Result<&[u8]> {
-        todo!("Not implemented")
+    /// Read up to 32 bits, the first bit read ends up in the highest bit of the result.
+    /// Reading 0 bits returns 0.
+    pub fn read_bits(&mut self, count: u8) -> io::Result<u32> {
+        let result = self.peek_bits(count)?;
+        self.consume(count);
+        Ok(result)
+    }
+
+    /// Same as `read_bits` without advancing the position.
+    pub fn peek_bits(&mut self, count: u8) -> io::Result<u32> {
+        if count > 32 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't read more than 32 bits",
+            ));
+        }
+        if count == 0 {
+            return Ok(0);
+        }
+
+        self.fill(count)?;
+
+        Ok((self.cache >> (64 - count)) as u32)
+    }
+
+    pub fn skip_bits(&mut self, mut count: u64) -> io::Result<()> {
+        while count > 0 {
+            let n_bits = count.min(32) as u8;
+            self.read_bits(n_bits)?;
+            count -= n_bits as u64;
+        }
+        Ok(())
+    }
+
+    /// Number of bits consumed since the start of inner
+    pub fn bit_position(&self) -> u64 {
+        self.position
+    }
+
+    /// Discard the remaining bits of a partially read byte
+    pub fn align_to_byte(&mut self) {
+        let n_bits = ((8 - self.position % 8) % 8) as u8;
+        self.consume(n_bits);
+    }
+
+    /// Returns inner together with the number of bits already taken from it
+    /// but not consumed yet.
+    pub fn into_inner(self) -> (R, u8) {
+        (self.inner, self.cached_bits_count)
     }
 
     pub fn read_u8(&mut self, count: u8) -> io::Result<u8> {
         if count > 8 {
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "can't read more than 8 bits from byte"));
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't read more than 8 bits from byte",
+            ));
         }
         if count == 0 {
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "value for count can't be zero"));
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "value for count can't be zero",
+            ));
         }
 
-        // fill up cache
-        if self.cached_bits_count < count {
-            let next_byte = self.inner.read_u8()?;
+        Ok(self.read_bits(count)? as u8)
+    }
 
-            self.cache |= (next_byte as u16)
-                .checked_shl((8 - self.cached_bits_count) as u32)
-                .unwrap();
+    fn fill(&mut self, count: u8) -> io::Result<()> {
+        while self.cached_bits_count < count {
+            let next_byte = self.inner.read_u8()?;
 
+            self.cache |= (next_byte as u64) << (56 - self.cached_bits_count);
             self.cached_bits_count += 8;
         }
+        Ok(())
+    }
 
-        self.cache = self.cache.rotate_left(count as u32);
-        let result = self.cache as u8;
-
-        self.cache &= 0xFF00;
+    fn consume(&mut self, count: u8) {
+        debug_assert!(count <= self.cached_bits_count);
+        self.cache = self.cache.checked_shl(count as u32).unwrap_or(0);
         self.cached_bits_count -= count;
+        self.position += count as u64;
+    }
+}
+
+/// Buffered MSB first bits, as consumed by table driven decoders.
+pub trait BitSource {
+    /// Top up the buffered bits to at least 56 unless the input is exhausted
+    fn refill(&mut self) -> io::Result<()>;
 
+    /// Number of buffered bits
+    fn available(&self) -> u32;
+
+    /// Next 1 to 32 buffered bits without consuming them, zero padded past the input end
+    fn peek(&self, n: u32) -> u32;
+
+    /// Drop n of the buffered bits
+    fn consume(&mut self, n: u32);
+}
+
+/// Reads bits MSB first from a slice, loading whole words at a time.
+///
+/// Cloning is cheap, so a copy can be kept to backtrack to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SliceBitReader<'a> {
+    data: &'a [u8],
+    /// Next byte not loaded into bits
+    pos: usize,
+    /// Buffered bits, left aligned. Bits past `count` are either zero
+    /// or already hold the following input bits.
+    bits: u64,
+    count: u32,
+}
+
+impl<'a> SliceBitReader<'a> {
+    pub fn new(data: &'a [u8]) -> SliceBitReader<'a> {
+        SliceBitReader {
+            data,
+            pos: 0,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    /// Same as `BitReader::read_bits`
+    pub fn read_bits(&mut self, count: u8) -> io::Result<u32> {
+        let result = self.peek_bits(count)?;
+        self.consume(count as u32);
         Ok(result)
     }
+
+    /// Same as `read_bits` without advancing the position.
+    pub fn peek_bits(&mut self, count: u8) -> io::Result<u32> {
+        if count > 32 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't read more than 32 bits",
+            ));
+        }
+        if count == 0 {
+            return Ok(0);
+        }
+        if self.count < count as u32 {
+            self.refill()?;
+            if self.count < count as u32 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "not enough bits left",
+                ));
+            }
+        }
+
+        Ok(self.peek(count as u32))
+    }
+
+    pub fn skip_bits(&mut self, count: u64) -> io::Result<()> {
+        if count > self.remaining_bits() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "not enough bits left",
+            ));
+        }
+
+        let buffered = count.min(self.count as u64);
+        self.consume(buffered as u32);
+
+        // skip whole bytes without loading them, then the remaining bits
+        let count = count - buffered;
+        if count > 0 {
+            // drop input bits loaded ahead of pos
+            self.bits = 0;
+            self.pos += (count / 8) as usize;
+        }
+        self.read_bits((count % 8) as u8)?;
+
+        Ok(())
+    }
+
+    /// Number of bits consumed since the start of the slice
+    pub fn bit_position(&self) -> u64 {
+        self.pos as u64 * 8 - self.count as u64
+    }
+
+    /// Number of bits left to read
+    pub fn remaining_bits(&self) -> u64 {
+        (self.data.len() - self.pos) as u64 * 8 + self.count as u64
+    }
+
+    /// Discard the remaining bits of a partially read byte
+    pub fn align_to_byte(&mut self) {
+        self.consume(self.count % 8);
+    }
+}
+
+impl BitSource for SliceBitReader<'_> {
+    fn refill(&mut self) -> io::Result<()> {
+        if let Some(word) = self.data.get(self.pos..self.pos + 8) {
+            let word = u64::from_be_bytes(word.try_into().expect("8 bytes"));
+            self.bits |= word >> self.count;
+            let n_bytes = (63 - self.count) / 8;
+            self.pos += n_bytes as usize;
+            self.count += n_bytes * 8;
+            return Ok(());
+        }
+
+        while self.count <= 56 && self.pos < self.data.len() {
+            self.bits |= (self.data[self.pos] as u64) << (56 - self.count);
+            self.pos += 1;
+            self.count += 8;
+        }
+
+        Ok(())
+    }
+
+    fn available(&self) -> u32 {
+        self.count
+    }
+
+    fn peek(&self, n: u32) -> u32 {
+        debug_assert!((1..=32).contains(&n));
+        (self.bits >> (64 - n)) as u32
+    }
+
+    fn consume(&mut self, n: u32) {
+        debug_assert!(n <= self.count);
+        self.bits = self.bits.checked_shl(n).unwrap_or(0);
+        self.count -= n;
+    }
 }
 
 #[cfg(test)]
 mod test {
 
     use super::*;
-    use std::io::{Cursor, self};
+    use std::io::{self, Cursor};
 
     #[test]
     fn read_u8() {
-        let mut input: [u8; 2] = [0b1110_0000, 0b0101_0101];
+        let input: [u8; 2] = [0b1110_0000, 0b0101_0101];
         let mut bit_reader = BitReader::new(&input[..]);
 
         let byte = bit_reader.read_u8(3);
@@ -85,25 +285,34 @@ mod test {
 
     #[test]
     fn read_u8_err_invalid_input() {
-        let mut input: [u8; 2] = [0b1110_0000, 0b0101_0101];
+        let input: [u8; 2] = [0b1110_0000, 0b0101_0101];
         let mut bit_reader = BitReader::new(&input[..]);
 
-        assert_eq!(bit_reader.read_u8(0).map_err(|e| e.kind()), Err(io::ErrorKind::InvalidInput));
-        assert_eq!(bit_reader.read_u8(9).map_err(|e| e.kind()), Err(io::ErrorKind::InvalidInput));
+        assert_eq!(
+            bit_reader.read_u8(0).map_err(|e| e.kind()),
+            Err(io::ErrorKind::InvalidInput)
+        );
+        assert_eq!(
+            bit_reader.read_u8(9).map_err(|e| e.kind()),
+            Err(io::ErrorKind::InvalidInput)
+        );
     }
 
     #[test]
     fn read_u8_err_unexpected_eof() {
-        let mut input: [u8; 1] = [0b1110_0000];
+        let input: [u8; 1] = [0b1110_0000];
         let mut bit_reader = BitReader::new(&input[..]);
 
         assert!(bit_reader.read_u8(8).is_ok());
-        assert_eq!(bit_reader.read_u8(1).map_err(|e| e.kind()), Err(io::ErrorKind::UnexpectedEof));
+        assert_eq!(
+            bit_reader.read_u8(1).map_err(|e| e.kind()),
+            Err(io::ErrorKind::UnexpectedEof)
+        );
     }
 
     #[test]
     fn offset() {
-        let mut input: [u8; 2] = [0b1110_0000, 0b0101_1111];
+        let input: [u8; 2] = [0b1110_0000, 0b0101_1111];
         let mut bit_reader = BitReader::with_offset(4, &input[..]).unwrap();
 
         let byte = bit_reader.read_u8(8);
@@ -113,7 +322,121 @@ mod test {
 
     #[test]
     fn offset_larger_than_input() {
-        let mut input: [u8; 2] = [0b1110_0000, 0b0101_1111];
-        assert_eq!(BitReader::with_offset(12, &input[..]).map_err(|e| e.kind() ), Err(std::io::ErrorKind::InvalidInput));
+        let input: [u8; 2] = [0b1110_0000, 0b0101_1111];
+        assert_eq!(
+            BitReader::with_offset(12, &input[..]).map_err(|e| e.kind()),
+            Err(std::io::ErrorKind::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn read_bits() {
+        let input: [u8; 6] = [0xDE, 0xAD, 0xBE, 0xEF, 0x12, 0x34];
+        let mut bit_reader = BitReader::new(&input[..]);
+
+        assert_eq!(bit_reader.read_bits(4).unwrap(), 0xD);
+        assert_eq!(bit_reader.read_bits(32).unwrap(), 0xEADBEEF1);
+        assert_eq!(bit_reader.read_bits(0).unwrap(), 0);
+        assert_eq!(bit_reader.read_bits(12).unwrap(), 0x234);
+        assert_eq!(
+            bit_reader.read_bits(33).map_err(|e| e.kind()),
+            Err(io::ErrorKind::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn peek_and_skip() {
+        let input: [u8; 3] = [0b1010_1100, 0xFF, 0x0F];
+        let mut bit_reader = BitReader::new(&input[..]);
+
+        assert_eq!(bit_reader.peek_bits(3).unwrap(), 0b101);
+        assert_eq!(bit_reader.peek_bits(24).unwrap(), 0xACFF0F);
+        assert_eq!(bit_reader.bit_position(), 0);
+
+        bit_reader.skip_bits(4).unwrap();
+        assert_eq!(bit_reader.bit_position(), 4);
+        assert_eq!(bit_reader.read_bits(6).unwrap(), 0b11_0011);
+        assert_eq!(
+            bit_reader.peek_bits(16).map_err(|e| e.kind()),
+            Err(io::ErrorKind::UnexpectedEof)
+        );
+        assert_eq!(bit_reader.read_bits(14).unwrap(), 0b11_1111_0000_1111);
+    }
+
+    #[test]
+    fn align_to_byte() {
+        let input: [u8; 3] = [0xFF, 0x5A, 0x01];
+        let mut bit_reader = BitReader::with_offset(8, &input[..]).unwrap();
+
+        bit_reader.align_to_byte();
+        assert_eq!(bit_reader.bit_position(), 8);
+
+        bit_reader.read_bits(3).unwrap();
+        bit_reader.align_to_byte();
+        assert_eq!(bit_reader.bit_position(), 16);
+        assert_eq!(bit_reader.read_bits(8).unwrap(), 0x01);
+    }
+
+    #[test]
+    fn into_inner() {
+        let mut bit_reader = BitReader::new(Cursor::new([0xAB, 0xCD]));
+
+        bit_reader.read_bits(3).unwrap();
+        let (inner, leftover) = bit_reader.into_inner();
+        assert_eq!(leftover, 5);
+        assert_eq!(inner.position(), 1);
+    }
+
+    #[test]
+    fn slice_matches_reader() {
+        let input: Vec<u8> = (0..100_u32).map(|i| (i * 37 + 11) as u8).collect();
+        let mut bit_reader = BitReader::new(&input[..]);
+        let mut slice_reader = SliceBitReader::new(&input);
+
+        for count in (0..=32).cycle().take(40) {
+            assert_eq!(
+                slice_reader.read_bits(count).unwrap(),
+                bit_reader.read_bits(count).unwrap()
+            );
+            assert_eq!(slice_reader.bit_position(), bit_reader.bit_position());
+        }
+        assert_eq!(
+            slice_reader.remaining_bits(),
+            800 - bit_reader.bit_position()
+        );
+    }
+
+    #[test]
+    fn slice_remaining_bits() {
+        let input: [u8; 3] = [0b1010_1100, 0xFF, 0x0F];
+        let mut slice_reader = SliceBitReader::new(&input);
+
+        assert_eq!(slice_reader.peek_bits(24).unwrap(), 0xACFF0F);
+        slice_reader.skip_bits(5).unwrap();
+        assert_eq!(slice_reader.remaining_bits(), 19);
+
+        slice_reader.align_to_byte();
+        assert_eq!(slice_reader.bit_position(), 8);
+        assert_eq!(
+            slice_reader.read_bits(17).map_err(|e| e.kind()),
+            Err(io::ErrorKind::UnexpectedEof)
+        );
+        assert_eq!(slice_reader.read_bits(16).unwrap(), 0xFF0F);
+        assert_eq!(slice_reader.remaining_bits(), 0);
+    }
+
+    #[test]
+    fn slice_backtracking() {
+        let input: Vec<u8> = (0..=255).collect();
+        let mut slice_reader = SliceBitReader::new(&input);
+
+        slice_reader.skip_bits(1000).unwrap();
+        let saved = slice_reader.clone();
+        let value = slice_reader.read_bits(20).unwrap();
+
+        slice_reader = saved;
+        assert_eq!(slice_reader.bit_position(), 1000);
+        assert_eq!(slice_reader.read_bits(20).unwrap(), value);
+        assert_eq!(value, 0x7D7E7);
     }
 }
@@ -1,56 +1,96 @@
 use std::io::{Result, Write};
 
-pub struct BitWriter<W> {
-    inner: W,
+/// Writes bits MSB first to inner. Pending bits are padded with zeros
+/// and written on `flush`, `into_inner` or drop.
+pub struct BitWriter<W: Write> {
+    inner: Option<W>, // only `None` once taken by into_inner
     cached_bits_count: u8,
     cache: u8,
+    position: u64, // bits written since the start of inner
 }
 
 impl<W: Write> BitWriter<W> {
     pub fn new(inner: W) -> BitWriter<W> {
         BitWriter {
-            inner,
+            inner: Some(inner),
             cached_bits_count: 0,
             cache: 0,
+            position: 0,
         }
     }
 
     /// Bit offset from the byte at the starting position of inner.
     pub fn with_offset(offset: u8, inner: W) -> BitWriter<W> {
+        assert!(offset < 8, "offset is larger than 7 bits!");
+
         BitWriter {
-            inner,
+            inner: Some(inner),
             cached_bits_count: offset,
             cache: 0,
+            position: offset as u64,
         }
     }
 
+    /// Write the highest `count` bits of byte.
+    /// Returns the number of bytes written to inner.
     pub fn write_u8(&mut self, byte: u8, count: u8) -> Result<usize> {
         assert!(count <= 8, "count is larger than fits inside 8 bits!");
 
+        // drop the unused low bits
+        let byte = byte & (0xFF00_u16 >> count) as u8;
+
         let mut bytes_written = 0;
 
+        // number of bits that fit into the current cache
+        let free_bits = 8 - self.cached_bits_count;
+
         // fill cache
         self.cache |= byte >> self.cached_bits_count;
 
         // update count
         self.cached_bits_count += count;
+        self.position += count as u64;
 
         if self.cached_bits_count >= 8 {
             // flush cache
-            bytes_written += self.inner.write(&[self.cache])?;
+            let cache = self.cache;
+            self.inner_mut().write_all(&[cache])?;
+            bytes_written += 1;
 
             // update with number of remaining bits
             self.cached_bits_count %= 8;
 
-            if self.cached_bits_count > 0 {
-                // new cache with remaining bits
-                self.cache = byte << (8 - self.cached_bits_count);
-            }
+            // new cache with remaining bits
+            self.cache = if self.cached_bits_count > 0 {
+                byte << free_bits
+            } else {
+                0
+            };
         }
 
         Ok(bytes_written)
     }
 
+    /// Write the lowest `count` bits of value, counterpart to `BitReader::read_bits`.
+    /// Returns the number of bytes written to inner.
+    pub fn write_n_bits(&mut self, value: u32, count: u8) -> Result<usize> {
+        assert!(count <= 32, "count is larger than fits inside 32 bits!");
+
+        let mut bytes_written = 0;
+        let mut remaining = count;
+
+        while remaining > 0 {
+            let n_bits = remaining.min(8);
+            let chunk = (value >> (remaining - n_bits)) as u8;
+            bytes_written += self.write_u8(chunk << (8 - n_bits), n_bits)?;
+            remaining -= n_bits;
+        }
+
+        Ok(bytes_written)
+    }
+
+    /// Write the first `count` bits of buf.
+    /// Returns the number of bytes written to inner.
     pub fn write_bits(&mut self, buf: &[u8], mut count: u64) -> Result<usize> {
         assert!(
             count <= buf.len() as u64 * 8,
@@ -59,37 +99,73 @@ impl<W: Write> BitWriter<W> {
 
         let mut bytes_written = 0;
 
-        // write all filled bytes
-        for byte in &buf[..buf.len() - 1] {
-            bytes_written += self.write_u8(*byte, 8)?;
-            count -= 8;
+        for byte in buf {
+            if count == 0 {
+                break;
+            }
+            let n_bits = count.min(8) as u8;
+            bytes_written += self.write_u8(*byte, n_bits)?;
+            count -= n_bits as u64;
         }
 
-        // write trailing
-        bytes_written += self.write_u8(buf[buf.len() - 1], count as u8)?;
-
         Ok(bytes_written)
     }
 
+    /// Number of bits written since the start of inner, including pending bits
+    pub fn bit_position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner
+            .as_ref()
+            .expect("inner is present until into_inner")
+    }
+
+    fn inner_mut(&mut self) -> &mut W {
+        self.inner
+            .as_mut()
+            .expect("inner is present until into_inner")
+    }
+
+    /// Flushes pending bits and returns inner.
     pub fn into_inner(mut self) -> Result<W> {
         self.flush()?;
-        Ok(self.inner)
+
+        Ok(self
+            .inner
+            .take()
+            .expect("inner is present until into_inner"))
     }
 }
 
 impl<W: Write> Write for BitWriter<W> {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        Ok(buf
-            .iter()
-            .map(|byte| self.write_u8(*byte, 8).unwrap() )
-            .sum()
-        )
+        for byte in buf {
+            self.write_u8(*byte, 8)?;
+        }
+        Ok(buf.len())
     }
 
+    /// Pads pending bits with zeros up to the next byte boundary.
     fn flush(&mut self) -> Result<()> {
-        self.inner.write(&[self.cache])?;
-        self.cached_bits_count = 0;
-        Ok(())
+        if self.cached_bits_count > 0 {
+            let cache = self.cache;
+            self.inner_mut().write_all(&[cache])?;
+            self.position += (8 - self.cached_bits_count) as u64;
+            self.cached_bits_count = 0;
+            self.cache = 0;
+        }
+        self.inner_mut().flush()
+    }
+}
+
+impl<W: Write> Drop for BitWriter<W> {
+    fn drop(&mut self) {
+        // errors can't be reported from drop, use into_inner to handle them
+        if self.inner.is_some() {
+            let _ = self.flush();
+        }
     }
 }
 
@@ -124,7 +200,10 @@ mod test {
         assert_eq!(bit_writer.write_u8(0b1000_0000, 1).unwrap(), 1);
         assert_eq!(bit_writer.cached_bits_count, 0, "0 bits in cache");
 
-        assert_eq!(bit_writer.inner.into_inner(), [0b1111_1111, 0b0000_0000]);
+        assert_eq!(
+            bit_writer.get_ref().get_ref()[..],
+            [0b1111_1111, 0b0000_0000]
+        );
     }
 
     #[test]
@@ -133,7 +212,12 @@ mod test {
         let output_buf = Cursor::new(&mut output[..]);
         let mut bit_writer = BitWriter::new(output_buf);
 
-        assert_eq!(bit_writer.write_bits(&[0b0000_1111, 0b1110_0000], 10).unwrap(), 1);
+        assert_eq!(
+            bit_writer
+                .write_bits(&[0b0000_1111, 0b1110_0000], 10)
+                .unwrap(),
+            1
+        );
         assert_eq!(bit_writer.cached_bits_count, 2, "2 bits in cache");
         assert_eq!(
             bit_writer.cache & 0b1100_0000,
@@ -141,7 +225,10 @@ mod test {
             "2 bits are cached"
         );
 
-        assert_eq!(bit_writer.inner.into_inner(), [0b0000_1111, 0b0000_0000]);
+        assert_eq!(
+            bit_writer.get_ref().get_ref()[..],
+            [0b0000_1111, 0b0000_0000]
+        );
     }
 
     #[test]
@@ -151,7 +238,68 @@ mod test {
         let output_buf = Cursor::new(&mut output[..]);
         let mut bit_writer = BitWriter::new(output_buf);
 
-        assert_eq!(bit_writer.write_bits(&[0b0000_1111, 0b1110_0000], 9).unwrap(), 1);
-        assert_eq!(bit_writer.flush().map_err(|e| e.kind()), Err(std::io::ErrorKind::UnexpectedEof));
+        assert_eq!(
+            bit_writer
+                .write_bits(&[0b0000_1111, 0b1110_0000], 9)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            bit_writer.flush().map_err(|e| e.kind()),
+            Err(std::io::ErrorKind::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn unused_bits_are_masked() {
+        let mut bit_writer = BitWriter::new(Vec::new());
+
+        bit_writer.write_u8(0b1011_1111, 2).unwrap();
+        bit_writer.write_u8(0b0111_1111, 1).unwrap();
+
+        assert_eq!(bit_writer.into_inner().unwrap(), [0b1000_0000]);
+    }
+
+    #[test]
+    fn flush_only_pads_pending_bits() {
+        let mut bit_writer = BitWriter::new(Vec::new());
+
+        bit_writer.write_u8(0xAB, 8).unwrap();
+        bit_writer.flush().unwrap();
+        bit_writer.flush().unwrap();
+        assert_eq!(bit_writer.get_ref(), &[0xAB]);
+
+        bit_writer.write_u8(0b1000_0000, 1).unwrap();
+        bit_writer.flush().unwrap();
+        bit_writer.flush().unwrap();
+        assert_eq!(bit_writer.bit_position(), 16);
+        assert_eq!(bit_writer.into_inner().unwrap(), [0xAB, 0x80]);
+    }
+
+    #[test]
+    fn write_n_bits() {
+        let mut bit_writer = BitWriter::new(Vec::new());
+
+        bit_writer.write_n_bits(0xD, 4).unwrap();
+        bit_writer.write_n_bits(0xEADBEEF1, 32).unwrap();
+        bit_writer.write_n_bits(0xFFFF, 0).unwrap();
+        assert_eq!(bit_writer.bit_position(), 36);
+        bit_writer.write_n_bits(0xF234, 12).unwrap();
+
+        assert_eq!(
+            bit_writer.into_inner().unwrap(),
+            [0xDE, 0xAD, 0xBE, 0xEF, 0x12, 0x34]
+        );
+    }
+
+    #[test]
+    fn flush_on_drop() {
+        let mut output = Vec::new();
+        {
+            let mut bit_writer = BitWriter::new(&mut output);
+            bit_writer.write_n_bits(0b101, 3).unwrap();
+            bit_writer.write_all(&[0xFF]).unwrap();
+        }
+        assert_eq!(output, [0b1011_1111, 0b1110_0000]);
     }
 }
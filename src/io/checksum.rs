@@ -0,0 +1,37 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+/// Rolling checksum over the little endian u32 words of bytes.
+///
+/// Each word is XORed into the sum shifted left by one, and the bit shifted
+/// out is carried around into the lowest bit. Trailing bytes that don't fill
+/// a whole word are ignored.
+pub fn checksum(bytes: &[u8]) -> u32 {
+    bytes.chunks_exact(4).fold(0, |sum, word| {
+        (sum >> 31) | ((sum << 1) ^ LittleEndian::read_u32(word))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn empty() {
+        assert_eq!(checksum(&[]), 0);
+        assert_eq!(checksum(&[0xFF, 0xFF, 0xFF]), 0);
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(checksum(&[1, 0, 0, 0]), 1);
+        assert_eq!(checksum(&[1, 0, 0, 0, 1, 0, 0, 0]), 3);
+        assert_eq!(checksum(&[1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]), 6);
+    }
+
+    #[test]
+    fn carries_highest_bit() {
+        assert_eq!(checksum(&[0, 0, 0, 0x80, 0, 0, 0, 0]), 1);
+        assert_eq!(checksum(&[0, 0, 0, 0xC0, 0, 0, 0, 0]), 0x8000_0001);
+    }
+}
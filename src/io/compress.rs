@@ -0,0 +1,470 @@
+use crate::io::bitwriter::BitWriter;
+use crate::io::decompress::{SymbolTable, HUFFMAN_TABLE, LENGTH_OFFSET, LZ_DIST};
+use std::io::prelude::*;
+
+/// Shortest back reference the format can express
+const MIN_MATCH: usize = 4;
+/// Longest back reference, reached with symbol 271 and 8 extra bits
+const MAX_MATCH: usize = 521;
+/// Farthest back reference, reached with distance code 7
+const MAX_DISTANCE: usize = 0xFFFF;
+/// Short matches further away than this cost more bits than plain literals
+const MIN_MATCH_MAX_DISTANCE: usize = 0x1000;
+
+/// Number of symbols written between two alphabet rebuilds (symbol 272)
+const REBUILD_INTERVAL: usize = 0x1000;
+
+const HASH_BITS: u32 = 15;
+/// Number of previous positions tried while looking for a match
+const MAX_CHAIN: usize = 128;
+const NO_POS: u32 = u32::MAX;
+
+const SYMBOL_REBUILD: u16 = 272;
+const SYMBOL_END: u16 = 273;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+impl Token {
+    /// Symbol of the alphabet representing this token
+    fn symbol(&self) -> u16 {
+        match *self {
+            Token::Literal(byte) => byte as u16,
+            Token::Match { length, .. } => length_code(length as usize).0,
+        }
+    }
+}
+
+/// Symbol, extra bit count and extra bit value encoding a copy length
+fn length_code(length: usize) -> (u16, u8, u16) {
+    if length < 12 {
+        return (256 + (length - MIN_MATCH) as u16, 0, 0);
+    }
+
+    for (i, offset) in LENGTH_OFFSET.iter().enumerate() {
+        let n_bits = i as u8 + 1;
+        let min_length = MIN_MATCH + *offset as usize;
+        if length < min_length + (1 << n_bits) {
+            return (264 + i as u16, n_bits, (length - min_length) as u16);
+        }
+    }
+
+    unreachable!("copy length exceeds {}", MAX_MATCH)
+}
+
+/// Distance code, extra bit count and extra bit value encoding a copy distance
+fn distance_code(distance: usize) -> (u8, u8, u16) {
+    for (code, (n_bits, base)) in LZ_DIST.iter().enumerate() {
+        let start = (*base as usize) << 9;
+        if distance < start + (256 << n_bits) {
+            return (code as u8, *n_bits, (distance - start) as u16);
+        }
+    }
+
+    unreachable!("copy distance exceeds {}", MAX_DISTANCE)
+}
+
+/// Hash chains over all 4 byte sequences seen so far
+struct MatchFinder<'a> {
+    data: &'a [u8],
+    head: Vec<u32>,
+    prev: Vec<u32>,
+}
+
+impl<'a> MatchFinder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        MatchFinder {
+            data,
+            head: vec![NO_POS; 1 << HASH_BITS],
+            prev: vec![NO_POS; data.len()],
+        }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let bytes = [
+            self.data[pos],
+            self.data[pos + 1],
+            self.data[pos + 2],
+            self.data[pos + 3],
+        ];
+        (u32::from_le_bytes(bytes).wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH > self.data.len() {
+            return;
+        }
+        let hash = self.hash(pos);
+        self.prev[pos] = self.head[hash];
+        self.head[hash] = pos as u32;
+    }
+
+    /// Longest match (length, distance) for the bytes at pos
+    fn find(&self, pos: usize) -> Option<(usize, usize)> {
+        if pos + MIN_MATCH > self.data.len() {
+            return None;
+        }
+
+        let max_length = MAX_MATCH.min(self.data.len() - pos);
+        let mut best: Option<(usize, usize)> = None;
+        let mut candidate = self.head[self.hash(pos)];
+
+        for _ in 0..MAX_CHAIN {
+            if candidate == NO_POS {
+                break;
+            }
+            let start = candidate as usize;
+            let distance = pos - start;
+            if distance > MAX_DISTANCE {
+                break;
+            }
+
+            let length = self.data[start..]
+                .iter()
+                .zip(&self.data[pos..pos + max_length])
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            let is_worth = length > MIN_MATCH || distance <= MIN_MATCH_MAX_DISTANCE;
+            if length >= MIN_MATCH && is_worth && best.is_none_or(|(l, _)| length > l) {
+                best = Some((length, distance));
+                if length == max_length {
+                    break;
+                }
+            }
+
+            candidate = self.prev[start];
+        }
+
+        best
+    }
+}
+
+/// Greedy LZ parse with one step of lazy evaluation
+fn tokenize(data: &[u8]) -> Vec<Token> {
+    let mut finder = MatchFinder::new(data);
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let found = finder.find(pos);
+        finder.insert(pos);
+
+        match found {
+            Some((length, _))
+                if length < MAX_MATCH && finder.find(pos + 1).is_some_and(|(l, _)| l > length) =>
+            {
+                tokens.push(Token::Literal(data[pos]));
+                pos += 1;
+            }
+            Some((length, distance)) => {
+                tokens.push(Token::Match {
+                    length: length as u16,
+                    distance: distance as u16,
+                });
+                for i in pos + 1..pos + length {
+                    finder.insert(i);
+                }
+                pos += length;
+            }
+            None => {
+                tokens.push(Token::Literal(data[pos]));
+                pos += 1;
+            }
+        }
+    }
+
+    tokens
+}
+
+/// Bit lengths of the 16 prefix codes minimizing the size of the next block.
+/// `usage` counts how often each alphabet index is going to be written.
+fn optimal_huffman(usage: &[u32; 274]) -> [(u8, u16); 16] {
+    const N: usize = 274;
+    const MAX_LENGTH: usize = 8;
+    const INF: u64 = u64::MAX;
+
+    let mut prefix = [0_u64; N + 1];
+    for (i, count) in usage.iter().enumerate() {
+        prefix[i + 1] = prefix[i] + *count as u64;
+    }
+
+    // cost[s][l]: bits spent so far with indices below s covered and last length l
+    let mut cost = vec![[INF; MAX_LENGTH + 1]; N + 1];
+    let mut parents = Vec::with_capacity(16);
+    cost[0][0] = 0;
+
+    for _ in 0..16 {
+        let mut next = vec![[INF; MAX_LENGTH + 1]; N + 1];
+        let mut parent = vec![[(0_u16, 0_u8); MAX_LENGTH + 1]; N + 1];
+
+        for (start, costs) in cost.iter().enumerate() {
+            for (prev_length, &current) in costs.iter().enumerate() {
+                if current == INF {
+                    continue;
+                }
+                for length in prev_length..=MAX_LENGTH {
+                    let end = (start + (1 << length)).min(N);
+                    // unary coded length difference plus extra bits of every covered index
+                    let bits = current
+                        + (length - prev_length + 1) as u64
+                        + (prefix[end] - prefix[start]) * length as u64;
+                    if bits < next[end][length] {
+                        next[end][length] = bits;
+                        parent[end][length] = (start as u16, prev_length as u8);
+                    }
+                }
+            }
+        }
+
+        cost = next;
+        parents.push(parent);
+    }
+
+    let mut length = (0..=MAX_LENGTH)
+        .min_by_key(|l| cost[N][*l])
+        .expect("non empty range");
+    let mut end = N;
+    let mut lengths = [0_u8; 16];
+    for (i, parent) in parents.iter().enumerate().rev() {
+        lengths[i] = length as u8;
+        let (start, prev_length) = parent[end][length];
+        end = start as usize;
+        length = prev_length as usize;
+    }
+
+    let mut huffman = [(0, 0); 16];
+    let mut base = 0;
+    for (entry, length) in huffman.iter_mut().zip(lengths) {
+        *entry = (length, base);
+        base += 1_u16 << length;
+    }
+    huffman
+}
+
+struct Encoder<W: Write> {
+    writer: BitWriter<W>,
+    symbol_table: SymbolTable,
+    huffman: [(u8, u16); 16],
+    /// Alphabet index of each symbol
+    indices: [u16; 274],
+}
+
+impl<W: Write> Encoder<W> {
+    fn new(inner: W) -> Self {
+        let symbol_table = SymbolTable::new();
+        Encoder {
+            writer: BitWriter::new(inner),
+            indices: symbol_table.indices(),
+            symbol_table,
+            huffman: HUFFMAN_TABLE,
+        }
+    }
+
+    /// Write the lowest `count` bits of value, most significant first
+    fn write_bits(&mut self, value: u16, count: u8) -> std::io::Result<()> {
+        self.writer.write_n_bits(value as u32, count)?;
+        Ok(())
+    }
+
+    fn write_symbol(&mut self, symbol: u16) -> std::io::Result<()> {
+        let index = self.indices[symbol as usize];
+
+        let code = self
+            .huffman
+            .iter()
+            .rposition(|(_length, base)| *base <= index)
+            .expect("first base is zero");
+        let (length, base) = self.huffman[code];
+        if index - base >= 1 << length {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "symbol not covered by the huffman table",
+            ));
+        }
+
+        self.write_bits(code as u16, 4)?;
+        self.write_bits(index - base, length)?;
+
+        self.symbol_table.symbol_at(index as usize);
+
+        Ok(())
+    }
+
+    fn write_token(&mut self, token: Token) -> std::io::Result<()> {
+        match token {
+            Token::Literal(byte) => self.write_symbol(byte as u16),
+            Token::Match { length, distance } => {
+                let (symbol, n_bits, extra) = length_code(length as usize);
+                self.write_symbol(symbol)?;
+                self.write_bits(extra, n_bits)?;
+
+                let (code, n_bits, value) = distance_code(distance as usize);
+                self.write_bits(code as u16, 3)?;
+                self.write_bits(value >> n_bits, 8)?;
+                self.write_bits(value & ((1 << n_bits) - 1), n_bits)
+            }
+        }
+    }
+
+    /// Emit symbol 272 and a new huffman table fitted to the upcoming symbols
+    fn rebuild(&mut self, upcoming: impl Iterator<Item = u16>) -> std::io::Result<()> {
+        self.write_symbol(SYMBOL_REBUILD)?;
+
+        self.symbol_table.rebuild_alphabet();
+        self.indices = self.symbol_table.indices();
+
+        let mut usage = [0; 274];
+        for symbol in upcoming {
+            usage[self.indices[symbol as usize] as usize] += 1;
+        }
+        self.huffman = optimal_huffman(&usage);
+
+        let mut prev_length = 0;
+        for (length, _base) in self.huffman {
+            for _ in prev_length..length {
+                self.write_bits(0, 1)?;
+            }
+            self.write_bits(1, 1)?;
+            prev_length = length;
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<W> {
+        self.write_symbol(SYMBOL_END)?;
+        self.writer.into_inner()
+    }
+}
+
+/// Compress data into the adaptive huffman + LZ stream read by `decompress`.
+///
+/// The alphabet is rebuilt every few thousand symbols, each time followed
+/// by a huffman table chosen to minimize the size of the next block.
+pub fn compress(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let tokens = tokenize(data);
+
+    let mut encoder = Encoder::new(Vec::new());
+
+    let mut blocks = tokens.chunks(REBUILD_INTERVAL).peekable();
+    let mut first = true;
+
+    while let Some(block) = blocks.next() {
+        if !first {
+            let terminator = match blocks.peek() {
+                Some(_) => SYMBOL_REBUILD,
+                None => SYMBOL_END,
+            };
+            let upcoming = block
+                .iter()
+                .map(Token::symbol)
+                .chain(std::iter::once(terminator));
+            encoder.rebuild(upcoming)?;
+        }
+        first = false;
+
+        for token in block {
+            encoder.write_token(*token)?;
+        }
+    }
+
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::io::decompress::decompress;
+
+    fn round_trip(data: &[u8]) {
+        let compressed = compress(data).unwrap();
+        let decompressed = decompress(&mut &compressed[..]).unwrap();
+        assert_eq!(decompressed.len(), data.len());
+        assert!(decompressed == data, "decompressed data differs");
+    }
+
+    /// Deterministic noise with a skewed byte distribution
+    fn noise(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let value = (state >> 16) as u8;
+                value & (value >> 3)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn empty() {
+        round_trip(&[]);
+    }
+
+    #[test]
+    fn literals() {
+        round_trip(b"abc");
+        round_trip(&(0..=255).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn repetitions() {
+        round_trip(&[0; 10_000]);
+        round_trip(&b"Die Siedler IV ".repeat(500));
+    }
+
+    #[test]
+    fn all_copy_lengths() {
+        let mut data = noise(600, 7);
+        for length in MIN_MATCH..=MAX_MATCH {
+            data.extend_from_within(..length);
+            data.push(length as u8);
+        }
+        round_trip(&data);
+    }
+
+    #[test]
+    fn far_references() {
+        let block = noise(0x9000, 3);
+        let mut data = block.clone();
+        data.extend(noise(0x6000, 5));
+        data.extend(&block);
+        round_trip(&data);
+    }
+
+    #[test]
+    fn alphabet_rebuilds() {
+        let data = noise(200_000, 11);
+        assert!(tokenize(&data).len() > 3 * REBUILD_INTERVAL);
+        round_trip(&data);
+    }
+
+    #[test]
+    fn compresses() {
+        let data = b"Die Siedler IV ".repeat(500);
+        assert!(compress(&data).unwrap().len() < data.len() / 20);
+    }
+
+    #[test]
+    fn length_codes() {
+        assert_eq!(length_code(4), (256, 0, 0));
+        assert_eq!(length_code(11), (263, 0, 0));
+        assert_eq!(length_code(12), (264, 1, 0));
+        assert_eq!(length_code(13), (264, 1, 1));
+        assert_eq!(length_code(14), (265, 2, 0));
+        assert_eq!(length_code(MAX_MATCH), (271, 8, 255));
+    }
+
+    #[test]
+    fn distance_codes() {
+        assert_eq!(distance_code(1), (0, 1, 1));
+        assert_eq!(distance_code(511), (0, 1, 511));
+        assert_eq!(distance_code(512), (1, 1, 0));
+        assert_eq!(distance_code(1024), (2, 2, 0));
+        assert_eq!(distance_code(MAX_DISTANCE), (7, 7, 0x7FFF));
+    }
+}
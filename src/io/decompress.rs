@@ -1,11 +1,48 @@
-use crate::io::bitreader::BitReader;
+use std::fmt;
 use std::io::prelude::*;
 
+use crate::io::bitreader::{BitSource, SliceBitReader};
+
 // https://www.rfc-editor.org/rfc/rfc1951
 // https://www.rfc-editor.org/rfc/rfc1952
 
+/// Initial (bit length, base index) pairs selected by the 4 bit prefix code
+pub(crate) const HUFFMAN_TABLE: [(u8, u16); 16] = [
+    (0x2, 0x0),
+    (0x3, 0x4),
+    (0x3, 0xC),
+    (0x4, 0x14),
+    (0x4, 0x24),
+    (0x4, 0x34),
+    (0x4, 0x44),
+    (0x4, 0x54),
+    (0x4, 0x64),
+    (0x4, 0x74),
+    (0x4, 0x84),
+    (0x4, 0x94),
+    (0x4, 0xA4),
+    (0x5, 0xB4),
+    (0x5, 0xD4),
+    (0x5, 0xF4),
+];
+
+/// Base copy lengths for the symbols 264..=271, which are followed by 1..=8 extra bits
+pub(crate) const LENGTH_OFFSET: [u16; 8] = [0x8, 0xA, 0xE, 0x16, 0x26, 0x46, 0x86, 0x106];
+
+/// (bit length, base) pairs of the back reference distance, selected by a 3 bit code
+pub(crate) const LZ_DIST: [(u8, u8); 8] = [
+    (1, 0x0),
+    (1, 0x1),
+    (2, 0x2),
+    (3, 0x4),
+    (4, 0x8),
+    (5, 0x10),
+    (6, 0x20),
+    (7, 0x40),
+];
+
 #[derive(Clone, Copy)]
-struct SymbolTable {
+pub(crate) struct SymbolTable {
     alphabet: [(u16, u32); 274], // contains values between 0 and 273 and their usage counts
 }
 
@@ -43,149 +80,513 @@ impl SymbolTable {
 
     /// Replace alphabet with consecutive numbers sorted by count and symbol
     pub fn rebuild_alphabet(&mut self) {
-        self.alphabet
-            .sort_by(|(symbol_a, count_a), (symbol_b, count_b)| {
-                match count_a.cmp(&count_b).reverse() {
-                    std::cmp::Ordering::Equal => symbol_a.cmp(&symbol_b).reverse(),
-                    ord => ord,
-                }
-            });
+        // The alphabet is still sorted from the previous rebuild apart from
+        // the symbols used since, so insertion sort only moves those.
+        let precedes = |(symbol_a, count_a): (u16, u32), (symbol_b, count_b): (u16, u32)| {
+            count_a > count_b || (count_a == count_b && symbol_a > symbol_b)
+        };
+
+        for i in 1..self.alphabet.len() {
+            let entry = self.alphabet[i];
+            let mut j = i;
+            while j > 0 && precedes(entry, self.alphabet[j - 1]) {
+                self.alphabet[j] = self.alphabet[j - 1];
+                j -= 1;
+            }
+            self.alphabet[j] = entry;
+        }
 
         for (_symbol, count) in self.alphabet.iter_mut() {
             *count /= 2;
         }
     }
+
+    /// Alphabet index of every symbol, the inverse of `symbol_at`
+    pub fn indices(&self) -> [u16; 274] {
+        let mut indices = [0; 274];
+        for (index, (symbol, _count)) in self.alphabet.iter().enumerate() {
+            indices[*symbol as usize] = index as u16;
+        }
+        indices
+    }
 }
 
-pub fn decompress(reader: &mut impl Read) -> Result<Vec<u8>, std::io::Error> {
-    let mut bit_reader = BitReader::new(reader);
+/// Ways in which a compressed stream can be malformed
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecompressError {
+    /// Decoded alphabet index lies outside of the 274 symbols
+    SymbolIndex(u16),
+    /// Rebuilt huffman table contains a code longer than 8 bits
+    CodeLength(usize),
+    /// Back reference reaches before the start of the output
+    Distance { offset: usize, position: usize },
+    /// Input ended in the middle of a symbol or before the end symbol
+    UnexpectedEof,
+    /// Output length differs from the expected one. When the output grows
+    /// too long, `actual` is the length it would have reached.
+    Length { expected: usize, actual: usize },
+}
 
-    let mut decrypt = Vec::<u8>::new();
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecompressError::SymbolIndex(index) => {
+                write!(f, "symbol index {} out of range of the symbol table", index)
+            }
+            DecompressError::CodeLength(length) => {
+                write!(f, "huffman code length {} exceeds 8 bits", length)
+            }
+            DecompressError::Distance { offset, position } => write!(
+                f,
+                "back reference of {} bytes at output position {}",
+                offset, position
+            ),
+            DecompressError::UnexpectedEof => write!(f, "compressed stream ended unexpectedly"),
+            DecompressError::Length { expected, actual } => write!(
+                f,
+                "decompressed length {} differs from expected {}",
+                actual, expected
+            ),
+        }
+    }
+}
 
-    let mut symbol_table = SymbolTable::new();
+impl std::error::Error for DecompressError {}
 
-    const HUFFMAN_TABLE: [(u8, u16); 16] = [
-        (0x2, 0x0),
-        (0x3, 0x4),
-        (0x3, 0xC),
-        (0x4, 0x14),
-        (0x4, 0x24),
-        (0x4, 0x34),
-        (0x4, 0x44),
-        (0x4, 0x54),
-        (0x4, 0x64),
-        (0x4, 0x74),
-        (0x4, 0x84),
-        (0x4, 0x94),
-        (0x4, 0xA4),
-        (0x5, 0xB4),
-        (0x5, 0xD4),
-        (0x5, 0xF4),
-    ];
+impl From<DecompressError> for std::io::Error {
+    fn from(e: DecompressError) -> Self {
+        let kind = match e {
+            DecompressError::UnexpectedEof => std::io::ErrorKind::UnexpectedEof,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, e)
+    }
+}
 
-    let mut huffman = HUFFMAN_TABLE;
+/// Size of the sliding window, covering the farthest back reference
+const WINDOW_SIZE: usize = 0x10000;
+const WINDOW_MASK: usize = WINDOW_SIZE - 1;
 
-    while let Ok(code) = bit_reader.read_u8(4) {
-        let (length, mut symbol_index) = huffman[code as usize];
+/// Size of the chunks read ahead from the compressed input
+const INPUT_CHUNK: usize = 0x1000;
 
-        if length > 0 {
-            let byte = bit_reader.read_u8(length)?;
-            symbol_index += byte as u16;
-        }
+/// Size of the chunks the output grows by when decompressing to a `Vec`
+const OUTPUT_CHUNK: usize = 0x10000;
 
-        assert!(symbol_index < 274, "index out of range of the symbol table");
+/// Largest output reserved up front from a length found in a header
+const MAX_RESERVED: usize = 1 << 20;
 
-        // retrieve symbol from alphabet
-        let symbol: u16 = symbol_table.symbol_at(symbol_index as usize);
+/// Bits looked up at once, a 4 bit prefix code and up to 8 extra bits
+const LOOKUP_BITS: u32 = 12;
 
-        let mut n_bytes = 4;
+/// MSB first bit buffer holding up to 64 bits of the input
+struct BitBuffer<R> {
+    inner: R,
+    input: Box<[u8]>,
+    input_pos: usize,
+    input_len: usize,
+    /// Buffered bits, left aligned. Bits past `count` are either zero
+    /// or already hold the following input bits.
+    bits: u64,
+    count: u32,
+}
 
-        match symbol {
-            0..=255 => {
-                // symbols within one byte are not compressed
-                decrypt.push(symbol as u8);
+impl<R: Read> BitBuffer<R> {
+    fn new(inner: R) -> Self {
+        BitBuffer {
+            inner,
+            input: vec![0; INPUT_CHUNK].into_boxed_slice(),
+            input_pos: 0,
+            input_len: 0,
+            bits: 0,
+            count: 0,
+        }
+    }
+}
 
+impl<R: Read> BitSource for BitBuffer<R> {
+    fn refill(&mut self) -> Result<(), std::io::Error> {
+        while self.count < 56 {
+            if self.input_len - self.input_pos >= 8 {
+                let word = &self.input[self.input_pos..self.input_pos + 8];
+                let word = u64::from_be_bytes(word.try_into().expect("8 bytes"));
+                self.bits |= word >> self.count;
+                let n_bytes = (63 - self.count) / 8;
+                self.input_pos += n_bytes as usize;
+                self.count += n_bytes * 8;
                 continue;
             }
-            256..=263 => {
-                let diff = symbol - 256;
-                n_bytes += diff as usize;
+
+            if self.input_pos == self.input_len {
+                self.input_pos = 0;
+                self.input_len = loop {
+                    match self.inner.read(&mut self.input) {
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                        result => break result?,
+                    }
+                };
+                if self.input_len == 0 {
+                    return Ok(());
+                }
+                continue;
             }
-            264..=271 => {
-                let length = (symbol - 263) as u8;
-                n_bytes += bit_reader.read_u8(length)? as usize;
 
-                const OFFSET: [u16; 8] = [0x8, 0xA, 0xE, 0x16, 0x26, 0x46, 0x86, 0x106];
-                let offset = OFFSET[(length - 1) as usize];
-                n_bytes += offset as usize;
+            self.bits |= (self.input[self.input_pos] as u64) << (56 - self.count);
+            self.input_pos += 1;
+            self.count += 8;
+        }
+
+        Ok(())
+    }
+
+    fn available(&self) -> u32 {
+        self.count
+    }
+
+    fn peek(&self, n: u32) -> u32 {
+        debug_assert!((1..=32).contains(&n));
+        (self.bits >> (64 - n)) as u32
+    }
+
+    fn consume(&mut self, n: u32) {
+        debug_assert!(n <= self.count);
+        self.bits = self.bits.checked_shl(n).unwrap_or(0);
+        self.count -= n;
+    }
+}
+
+fn read_bits(bits: &mut impl BitSource, n: u32) -> Result<u32, std::io::Error> {
+    if n == 0 {
+        return Ok(0);
+    }
+    if bits.available() < n {
+        bits.refill()?;
+        if bits.available() < n {
+            return Err(DecompressError::UnexpectedEof.into());
+        }
+    }
+    let value = bits.peek(n);
+    bits.consume(n);
+    Ok(value)
+}
+
+/// Table mapping the next `LOOKUP_BITS` of input to an alphabet index
+/// in the low 16 bits and the number of bits it occupies in the high 16 bits
+fn fill_lookup(table: &mut [u32], huffman: &[(u8, u16); 16]) {
+    for (entries, (length, base)) in table.chunks_exact_mut(1 << 8).zip(huffman) {
+        let shift = 8 - *length as u32;
+        let n_bits = (4 + *length as u32) << 16;
+        for (extra_bits, entry) in entries.iter_mut().enumerate() {
+            *entry = (*base as u32 + (extra_bits as u32 >> shift)) | n_bits;
+        }
+    }
+}
+
+/// Result of decoding a single symbol
+enum Step {
+    Literal(u8),
+    Copy { n_bytes: usize, offset: usize },
+    Rebuild,
+    End,
+}
+
+/// Decoder for the adaptive huffman + LZ stream over any bit source
+struct Decoder<B> {
+    bits: B,
+    symbol_table: SymbolTable,
+    lookup: Box<[u32]>,
+    window: Box<[u8]>,
+    /// Number of bytes decompressed so far
+    position: usize,
+    /// Expected length of the output, if known
+    expected: Option<usize>,
+    /// Pending back reference as (bytes left to copy, offset)
+    copy: (usize, usize),
+    finished: bool,
+    /// Error hit after some bytes were already handed out by `read`
+    error: Option<std::io::Error>,
+}
+
+impl<B: BitSource> Decoder<B> {
+    fn new(bits: B, expected: Option<usize>) -> Decoder<B> {
+        let mut lookup = vec![0; 1 << LOOKUP_BITS].into_boxed_slice();
+        fill_lookup(&mut lookup, &HUFFMAN_TABLE);
+
+        Decoder {
+            bits,
+            symbol_table: SymbolTable::new(),
+            lookup,
+            window: vec![0; WINDOW_SIZE].into_boxed_slice(),
+            position: 0,
+            expected,
+            copy: (0, 0),
+            finished: false,
+            error: None,
+        }
+    }
+
+    fn next_step(&mut self) -> Result<Step, std::io::Error> {
+        let bits = &mut self.bits;
+
+        if bits.available() < LOOKUP_BITS {
+            bits.refill()?;
+        }
+        let entry = self.lookup[bits.peek(LOOKUP_BITS) as usize];
+        let (symbol_index, n_bits) = ((entry & 0xFFFF) as u16, entry >> 16);
+
+        if n_bits > bits.available() {
+            // streams of known length may end without the end symbol
+            if bits.available() < 4 && self.expected == Some(self.position) {
+                return Ok(Step::End);
+            }
+            return Err(DecompressError::UnexpectedEof.into());
+        }
+        bits.consume(n_bits);
+
+        if symbol_index >= 274 {
+            return Err(DecompressError::SymbolIndex(symbol_index).into());
+        }
+
+        // retrieve symbol from alphabet
+        let symbol: u16 = self.symbol_table.symbol_at(symbol_index as usize);
+
+        let n_bytes = match symbol {
+            // symbols within one byte are not compressed
+            0..=255 => return Ok(Step::Literal(symbol as u8)),
+            256..=263 => 4 + (symbol - 256) as usize,
+            264..=271 => {
+                let length = (symbol - 263) as u32;
+                let offset = LENGTH_OFFSET[(length - 1) as usize];
+                4 + offset as usize + read_bits(bits, length)? as usize
             }
             272 => {
-                // some sort of reset
-                symbol_table.rebuild_alphabet();
+                self.symbol_table.rebuild_alphabet();
 
                 // rebuild huffman table
-                let mut tmp_length: i8 = 0;
+                let mut huffman = [(0, 0); 16];
+                let mut tmp_length = 0;
                 let mut tmp_base = 0;
 
                 for (length, symbol_index) in huffman.iter_mut() {
-                    tmp_length -= 1;
                     // count zeroes
-                    loop {
+                    while read_bits(bits, 1)? == 0 {
                         tmp_length += 1;
-                        if bit_reader.read_u8(1)? == 1 {
-                            break;
+                        if tmp_length > 8 {
+                            return Err(DecompressError::CodeLength(tmp_length).into());
                         }
                     }
 
                     *length = tmp_length as u8;
                     *symbol_index = tmp_base;
 
-                    tmp_base += (1 as u16) << *length;
+                    tmp_base += 1_u16 << *length;
                 }
 
-                continue;
-            }
-            273 => {
-                return Ok(decrypt);
+                fill_lookup(&mut self.lookup, &huffman);
+
+                return Ok(Step::Rebuild);
             }
+            273 => return Ok(Step::End),
             _ => {
                 return Err(std::io::Error::new(
                     std::io::ErrorKind::InvalidData,
                     "unexcpected symbol during deflate",
-                ));
+                ))
+            }
+        };
+
+        let (length, base_value) = LZ_DIST[read_bits(bits, 3)? as usize];
+
+        // 8 high bits followed by `length` low bits
+        let bitmask = read_bits(bits, 8 + length as u32)? as usize;
+
+        let offset = bitmask + ((base_value as usize) << 9);
+
+        if offset == 0 || offset > self.position {
+            return Err(DecompressError::Distance {
+                offset,
+                position: self.position,
             }
+            .into());
         }
 
-        let bit_value = bit_reader.read_u8(3)?;
-        const LZ_DIST: [(u8, u8); 8] = [
-            (1, 0x0),
-            (1, 0x1),
-            (2, 0x2),
-            (3, 0x4),
-            (4, 0x8),
-            (5, 0x10),
-            (6, 0x20),
-            (7, 0x40),
-        ];
-        let (length, base_value) = LZ_DIST[bit_value as usize];
+        Ok(Step::Copy { n_bytes, offset })
+    }
 
-        let bit_value = bit_reader.read_u8(8)?;
-        let copy_offset = (bit_value as usize).checked_shl(length as u32).unwrap();
+    /// Decompress into buf until it is full or the stream ends
+    fn fill(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        let mut n = 0;
+
+        loop {
+            let (remaining, offset) = self.copy;
+            if remaining > 0 {
+                let n_copy = remaining.min(buf.len() - n);
+                let src = (self.position - offset) & WINDOW_MASK;
+                let dst = self.position & WINDOW_MASK;
+                let out = &mut buf[n..n + n_copy];
+
+                if offset >= n_copy && src.max(dst) + n_copy <= WINDOW_SIZE {
+                    // neither overlapping nor wrapping around the window end
+                    self.window.copy_within(src..src + n_copy, dst);
+                    out.copy_from_slice(&self.window[dst..dst + n_copy]);
+                    self.position += n_copy;
+                } else {
+                    for out in out {
+                        let byte = self.window[(self.position - offset) & WINDOW_MASK];
+                        self.window[self.position & WINDOW_MASK] = byte;
+                        self.position += 1;
+                        *out = byte;
+                    }
+                }
+                n += n_copy;
+                self.copy.0 -= n_copy;
+            }
 
-        let bit_value = bit_reader.read_u8(length)?;
+            if n == buf.len() || self.finished {
+                break;
+            }
 
-        let bitmask = bit_value as usize | copy_offset;
+            let step = match self.next_step() {
+                Ok(step) => step,
+                Err(e) if n > 0 => {
+                    self.error = Some(e);
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let n_bytes = match step {
+                Step::Literal(_) => 1,
+                Step::Copy { n_bytes, .. } => n_bytes,
+                Step::Rebuild => 0,
+                Step::End => 0,
+            };
+            if let Some(expected) = self.expected {
+                let actual = self.position + n_bytes;
+                let too_short = matches!(step, Step::End) && actual < expected;
+                if actual > expected || too_short {
+                    let e = DecompressError::Length { expected, actual };
+                    if n > 0 {
+                        self.error = Some(e.into());
+                        break;
+                    }
+                    return Err(e.into());
+                }
+            }
 
-        let current_index = decrypt.len();
-        let offset = bitmask + (base_value as usize).checked_shl(9).unwrap();
+            match step {
+                Step::Literal(byte) => {
+                    self.window[self.position & WINDOW_MASK] = byte;
+                    self.position += 1;
+                    buf[n] = byte;
+                    n += 1;
+                }
+                Step::Copy { n_bytes, offset } => self.copy = (n_bytes, offset),
+                Step::Rebuild => {}
+                Step::End => self.finished = true,
+            }
+        }
 
-        let src_pos = current_index - offset;
+        Ok(n)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.fill(buf)
+    }
 
-        for i in src_pos..(src_pos + n_bytes) {
-            let prev_byte = decrypt.get(i).expect("index points to existing position");
-            decrypt.push(prev_byte.clone());
+    /// Decompress straight into the spare room of decrypt
+    fn read_to_end(mut self, decrypt: &mut Vec<u8>) -> Result<(), std::io::Error> {
+        loop {
+            let start = decrypt.len();
+            decrypt.resize(start + OUTPUT_CHUNK, 0);
+            let result = self.read(&mut decrypt[start..]);
+            decrypt.truncate(start + *result.as_ref().unwrap_or(&0));
+            if result? == 0 {
+                return Ok(());
+            }
         }
     }
+}
+
+/// Decoder for the adaptive huffman + LZ stream, yielding the
+/// decompressed bytes incrementally through `Read`.
+///
+/// Only the last 64 KiB of output are kept around to resolve back references.
+/// Input is read ahead in chunks, so wrap `inner` with `Read::take` when more
+/// data follows the compressed stream.
+/// Malformed input surfaces as `io::Error` wrapping a `DecompressError`.
+pub struct DecompressReader<R> {
+    decoder: Decoder<BitBuffer<R>>,
+}
+
+impl<R: Read> DecompressReader<R> {
+    pub fn new(inner: R) -> DecompressReader<R> {
+        DecompressReader {
+            decoder: Decoder::new(BitBuffer::new(inner), None),
+        }
+    }
+
+    /// Decoder failing with `DecompressError::Length` unless exactly
+    /// `n_bytes_decrypted` bytes are decompressed. Output is never
+    /// produced past that length.
+    pub fn with_expected_len(inner: R, n_bytes_decrypted: usize) -> DecompressReader<R> {
+        DecompressReader {
+            decoder: Decoder::new(BitBuffer::new(inner), Some(n_bytes_decrypted)),
+        }
+    }
+
+    /// Number of bytes decompressed so far
+    pub fn position(&self) -> usize {
+        self.decoder.position
+    }
+}
+
+impl<R: Read> Read for DecompressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        self.decoder.read(buf)
+    }
+}
+
+pub fn decompress(reader: &mut impl Read) -> Result<Vec<u8>, std::io::Error> {
+    let mut decrypt = Vec::<u8>::new();
+
+    DecompressReader::new(reader).read_to_end(&mut decrypt)?;
+
+    Ok(decrypt)
+}
+
+/// Decompress a stream that must yield exactly `n_bytes_decrypted` bytes
+pub fn decompress_exact(
+    reader: &mut impl Read,
+    n_bytes_decrypted: usize,
+) -> Result<Vec<u8>, std::io::Error> {
+    let mut decrypt = Vec::<u8>::with_capacity(n_bytes_decrypted.min(MAX_RESERVED));
+
+    DecompressReader::with_expected_len(reader, n_bytes_decrypted).read_to_end(&mut decrypt)?;
+
+    Ok(decrypt)
+}
+
+/// Decompress a stream held in memory, reading the input a word at a time
+pub fn decompress_slice(input: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let mut decrypt = Vec::<u8>::new();
+
+    Decoder::new(SliceBitReader::new(input), None).read_to_end(&mut decrypt)?;
+
+    Ok(decrypt)
+}
+
+/// Same as `decompress_exact` for a stream held in memory
+pub fn decompress_slice_exact(
+    input: &[u8],
+    n_bytes_decrypted: usize,
+) -> Result<Vec<u8>, std::io::Error> {
+    let mut decrypt = Vec::<u8>::with_capacity(n_bytes_decrypted.min(MAX_RESERVED));
+
+    Decoder::new(SliceBitReader::new(input), Some(n_bytes_decrypted)).read_to_end(&mut decrypt)?;
 
     Ok(decrypt)
 }
@@ -194,6 +595,7 @@ pub fn decompress(reader: &mut impl Read) -> Result<Vec<u8>, std::io::Error> {
 mod tests {
 
     use super::*;
+    use crate::io::bitreader::BitReader;
 
     #[test]
     fn test_reading_from_output() {
@@ -201,10 +603,184 @@ mod tests {
 
         let mut writer = crate::io::bitwriter::BitWriter::new(&mut output[..]);
         writer.write_bits(&[0b1111_0000], 4).unwrap();
-        writer.flush().unwrap();
+        writer.into_inner().unwrap();
 
         let mut reader = BitReader::new(&output[..]);
 
         assert_eq! {reader.read_u8(8).unwrap(), 0b1111_0000};
     }
+
+    #[test]
+    fn stream_matches_decompress() {
+        let data = b"Die Siedler IV - Gold Edition ".repeat(4000);
+        let compressed = crate::io::compress::compress(&data).unwrap();
+
+        let mut stream = DecompressReader::new(&compressed[..]);
+        let mut decrypt = Vec::new();
+        let mut buf = [0; 7];
+        loop {
+            let n = stream.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            decrypt.extend_from_slice(&buf[..n]);
+        }
+
+        assert_eq!(stream.position(), data.len());
+        assert!(decrypt == data);
+        assert!(decompress(&mut &compressed[..]).unwrap() == data);
+    }
+
+    #[test]
+    fn slice_matches_decompress() {
+        let data = b"Die Siedler IV - Gold Edition ".repeat(4000);
+        let compressed = crate::io::compress::compress(&data).unwrap();
+
+        assert!(decompress_slice(&compressed).unwrap() == data);
+        assert!(decompress_slice_exact(&compressed, data.len()).unwrap() == data);
+        assert!(decompress_slice_exact(&compressed, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn stream_from_take() {
+        let data = b"MapInfo".repeat(100);
+        let mut input = crate::io::compress::compress(&data).unwrap();
+        let n_bytes_encrypted = input.len() as u64;
+        input.extend_from_slice(b"next segment");
+
+        let mut reader = &input[..];
+        let mut stream = DecompressReader::new(reader.by_ref().take(n_bytes_encrypted));
+
+        let mut head = [0; 7];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(&head, b"MapInfo");
+
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest.len(), data.len() - head.len());
+        assert_eq!(reader, b"next segment");
+    }
+
+    fn decompress_error(input: &[u8], n_bytes_decrypted: Option<usize>) -> DecompressError {
+        let (result, slice_result) = match n_bytes_decrypted {
+            Some(n) => (
+                decompress_exact(&mut &input[..], n),
+                decompress_slice_exact(input, n),
+            ),
+            None => (decompress(&mut &input[..]), decompress_slice(input)),
+        };
+        let [e, slice_e] = [result, slice_result].map(|result| {
+            result
+                .expect_err("malformed input")
+                .get_ref()
+                .and_then(|e| e.downcast_ref::<DecompressError>())
+                .expect("error caused by DecompressError")
+                .clone()
+        });
+        assert_eq!(e, slice_e);
+        e
+    }
+
+    #[test]
+    fn symbol_index_out_of_range() {
+        // code 15 selects base 244 with 5 extra bits, 244 + 31 = 275
+        assert_eq!(
+            decompress_error(&[0b1111_1111, 0b1000_0000], None),
+            DecompressError::SymbolIndex(275)
+        );
+    }
+
+    #[test]
+    fn code_length_too_long() {
+        // code 15 + 28 selects symbol 272, followed by 9 zero bits of table data
+        assert_eq!(
+            decompress_error(&[0b1111_1110, 0, 0], None),
+            DecompressError::CodeLength(9)
+        );
+    }
+
+    #[test]
+    fn distance_before_start() {
+        // symbol 256 copies 4 bytes from offset 1 of an empty output
+        assert_eq!(
+            decompress_error(&[0, 0, 0b0100_0000], None),
+            DecompressError::Distance {
+                offset: 1,
+                position: 0
+            }
+        );
+    }
+
+    #[test]
+    fn truncated_stream() {
+        let data = b"Die Siedler IV ".repeat(50);
+        let compressed = crate::io::compress::compress(&data).unwrap();
+
+        for len in [0, 1, compressed.len() / 2] {
+            assert_eq!(
+                decompress_error(&compressed[..len], None),
+                DecompressError::UnexpectedEof
+            );
+        }
+    }
+
+    #[test]
+    fn expected_length() {
+        let data = b"Die Siedler IV ".repeat(50);
+        let compressed = crate::io::compress::compress(&data).unwrap();
+
+        assert!(decompress_exact(&mut &compressed[..], data.len()).unwrap() == data);
+
+        assert!(matches!(
+            decompress_error(&compressed, Some(100)),
+            DecompressError::Length { expected: 100, actual } if actual > 100
+        ));
+        assert_eq!(
+            decompress_error(&compressed, Some(data.len() + 1)),
+            DecompressError::Length {
+                expected: data.len() + 1,
+                actual: data.len()
+            }
+        );
+
+        // a length read from a corrupt header is not allocated up front
+        assert!(decompress_exact(&mut &compressed[..], usize::MAX).is_err());
+    }
+
+    #[test]
+    fn output_capped_at_expected_length() {
+        let data = [7; 1000];
+        let compressed = crate::io::compress::compress(&data).unwrap();
+
+        let mut stream = DecompressReader::with_expected_len(&compressed[..], 10);
+        let mut buf = [0; 100];
+        let n = stream.read(&mut buf).unwrap();
+        assert!(n <= 10);
+        assert!(buf[..n].iter().all(|byte| *byte == 7));
+        assert!(stream.read(&mut buf).is_err());
+        assert_eq!(stream.position(), n);
+    }
+
+    #[test]
+    fn rebuild_alphabet_sorts_by_count_and_symbol() {
+        let mut table = SymbolTable::new();
+        let mut state: u32 = 1;
+
+        for _ in 0..5 {
+            for _ in 0..2000 {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let index = (state >> 16) as usize % 274;
+                table.symbol_at(index.min(index / 4 + 10));
+            }
+
+            let mut expected = table.alphabet;
+            expected.sort_by(|(symbol_a, count_a), (symbol_b, count_b)| {
+                count_b.cmp(count_a).then(symbol_b.cmp(symbol_a))
+            });
+            expected.iter_mut().for_each(|(_symbol, count)| *count /= 2);
+
+            table.rebuild_alphabet();
+            assert_eq!(table.alphabet, expected);
+        }
+    }
 }
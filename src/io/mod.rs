@@ -1,5 +1,14 @@
 pub mod ara_crypt;
+pub mod archive;
 pub mod bitreader;
 pub mod bitwriter;
+pub mod checksum;
+pub mod compress;
 pub mod decompress;
-pub mod archive;
+pub mod overlay;
+pub mod segment;
+pub mod vfs;
+pub mod windows1252;
+
+#[cfg(test)]
+pub(crate) mod temp_dir;
@@ -0,0 +1,300 @@
+//! Loose files overriding the contents of .lib archives, the way the game
+//! resolves its assets.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::io::archive::{normalize, Archive};
+use crate::io::vfs::{Directory, Vfs, VfsEntry};
+
+/// Source of files in an `Overlay`
+pub type Layer = Box<dyn Vfs + Send + Sync>;
+
+/// File or directory seen through an `Overlay`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlayEntry {
+    path: PathBuf,
+    is_dir: bool,
+    layer: usize,
+}
+
+impl OverlayEntry {
+    /// Path relative to the layer root
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+    /// Index of the layer the entry comes from
+    pub fn layer(&self) -> usize {
+        self.layer
+    }
+}
+
+/// Ordered layers of loose directories, archives or any other `Vfs`.
+///
+/// Lookups return the file of the first layer containing it, listings merge all
+/// layers. Paths are matched case-insensitively with either separator, also on disk.
+#[derive(Default)]
+pub struct Overlay {
+    layers: Vec<Layer>,
+}
+
+impl fmt::Debug for Overlay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Overlay")
+            .field("layers", &self.layers.len())
+            .finish()
+    }
+}
+
+impl Overlay {
+    pub fn new() -> Overlay {
+        Overlay::default()
+    }
+
+    /// Add a layer below all previous ones
+    pub fn push<V: Vfs + Send + Sync + 'static>(&mut self, layer: V) -> &mut Overlay {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// Add the loose files below path as a layer
+    pub fn push_dir<P: AsRef<Path>>(&mut self, path: P) -> &mut Overlay {
+        self.push(Directory::new(path))
+    }
+
+    pub fn push_archive(&mut self, archive: Archive) -> &mut Overlay {
+        self.push(archive)
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// Contents of the first file found at path
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<Option<Vec<u8>>> {
+        for layer in &self.layers {
+            let data = layer.open(path.as_ref())?;
+            if data.is_some() {
+                return Ok(data);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Index of the layer that provides the file at path
+    pub fn find<P: AsRef<Path>>(&self, path: P) -> io::Result<Option<usize>> {
+        for (i, layer) in self.layers.iter().enumerate() {
+            if layer.is_file(path.as_ref())? {
+                return Ok(Some(i));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Merged contents of dir in all layers, use "" for the root.
+    /// Fails with `NotFound` if no layer has dir.
+    pub fn read_dir<P: AsRef<Path>>(&self, dir: P) -> io::Result<Vec<OverlayEntry>> {
+        let dir = dir.as_ref();
+        let mut seen = HashSet::new();
+        let mut entries = Vec::new();
+        let mut found = false;
+
+        for (layer_index, layer) in self.layers.iter().enumerate() {
+            let layer_entries = match layer.read_dir(dir) {
+                Ok(layer_entries) => layer_entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+
+            found = true;
+            for entry in layer_entries {
+                if seen.insert(normalize(&entry.path().to_string_lossy())) {
+                    entries.push(OverlayEntry {
+                        is_dir: entry.is_dir(),
+                        path: entry.path().to_path_buf(),
+                        layer: layer_index,
+                    });
+                }
+            }
+        }
+
+        if !found {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no directory {} in any layer", dir.display()),
+            ));
+        }
+        Ok(entries)
+    }
+
+    /// Every file that a lookup can return, with the layer providing it
+    pub fn files(&self) -> io::Result<Vec<OverlayEntry>> {
+        let mut seen = HashSet::new();
+        let mut entries = Vec::new();
+
+        for (layer_index, layer) in self.layers.iter().enumerate() {
+            for path in layer.files()? {
+                if seen.insert(normalize(&path.to_string_lossy())) {
+                    entries.push(OverlayEntry {
+                        path,
+                        is_dir: false,
+                        layer: layer_index,
+                    });
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+impl Vfs for Overlay {
+    fn open(&self, path: &Path) -> io::Result<Option<Vec<u8>>> {
+        Overlay::open(self, path)
+    }
+
+    fn is_file(&self, path: &Path) -> io::Result<bool> {
+        Ok(self.find(path)?.is_some())
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<VfsEntry>> {
+        Ok(Overlay::read_dir(self, dir)?
+            .into_iter()
+            .map(|entry| VfsEntry::new(entry.path, entry.is_dir))
+            .collect())
+    }
+
+    fn files(&self) -> io::Result<Vec<PathBuf>> {
+        Ok(Overlay::files(self)?
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::io::archive::ArchiveBuilder;
+    use crate::io::temp_dir::TempDir;
+    use crate::io::vfs::MemoryFs;
+    use std::fs;
+
+    /// Loose files in a mod directory over a base archive
+    fn overlay(name: &str) -> (Overlay, TempDir) {
+        let dir = TempDir::new(name);
+
+        let mut builder = ArchiveBuilder::new(Vec::new());
+        builder.add("gfx/0.gfx", b"base 0", false).unwrap();
+        builder.add("gfx/1.gfx", b"base 1", true).unwrap();
+        builder.add("snd/0.snd", b"sound", false).unwrap();
+        fs::write(dir.join("base.lib"), builder.finish().unwrap()).unwrap();
+
+        let loose = dir.join("mod");
+        fs::create_dir_all(loose.join("Gfx")).unwrap();
+        fs::write(loose.join("Gfx").join("1.GFX"), b"mod 1").unwrap();
+        fs::write(loose.join("Gfx").join("2.gfx"), b"mod 2").unwrap();
+
+        let mut overlay = Overlay::new();
+        overlay
+            .push_dir(&loose)
+            .push_archive(Archive::new(dir.join("base.lib")).unwrap());
+        (overlay, dir)
+    }
+
+    #[test]
+    fn first_layer_wins() {
+        let (overlay, _dir) = overlay("first_layer_wins");
+
+        assert_eq!(overlay.open("gfx/0.gfx").unwrap().unwrap(), b"base 0");
+        assert_eq!(overlay.open("gfx\\1.gfx").unwrap().unwrap(), b"mod 1");
+        assert_eq!(overlay.open("GFX/2.gfx").unwrap().unwrap(), b"mod 2");
+        assert_eq!(overlay.open("gfx/3.gfx").unwrap(), None);
+        assert_eq!(overlay.open("gfx/1.gfx/x").unwrap(), None);
+
+        assert_eq!(overlay.find("gfx/1.gfx").unwrap(), Some(0));
+        assert_eq!(overlay.find("snd/0.snd").unwrap(), Some(1));
+        assert_eq!(overlay.find("gfx").unwrap(), None);
+    }
+
+    #[test]
+    fn paths_stay_inside_layers() {
+        let (overlay, dir) = overlay("paths_stay_inside_layers");
+
+        // base.lib is next to the loose directory of the first layer
+        assert!(dir.join("mod").join("..").join("base.lib").is_file());
+        assert_eq!(overlay.open("../base.lib").unwrap(), None);
+        assert_eq!(overlay.open("..\\base.lib").unwrap(), None);
+        assert_eq!(overlay.open("gfx/../../base.lib").unwrap(), None);
+        assert_eq!(overlay.open(dir.join("base.lib")).unwrap(), None);
+        assert_eq!(
+            overlay.read_dir("..").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn listing_merges_layers() {
+        let (overlay, _dir) = overlay("listing_merges_layers");
+
+        let summary = |entries: Vec<OverlayEntry>| -> Vec<(String, bool, usize)> {
+            entries
+                .into_iter()
+                .map(|e| {
+                    (
+                        e.path().to_string_lossy().into_owned(),
+                        e.is_dir(),
+                        e.layer(),
+                    )
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            summary(overlay.read_dir("").unwrap()),
+            [("Gfx".into(), true, 0), ("snd".into(), true, 1)]
+        );
+        assert_eq!(
+            summary(overlay.read_dir("gfx").unwrap()),
+            [
+                ("Gfx/1.GFX".into(), false, 0),
+                ("Gfx/2.gfx".into(), false, 0),
+                ("gfx/0.gfx".into(), false, 1)
+            ]
+        );
+        assert_eq!(
+            overlay.read_dir("pal").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        let files = summary(overlay.files().unwrap());
+        assert_eq!(files.len(), 4);
+        assert!(files.contains(&("snd/0.snd".into(), false, 1)));
+        assert!(!files.contains(&("gfx/1.gfx".into(), false, 1)));
+    }
+
+    #[test]
+    fn any_vfs_as_layer() {
+        let (mut overlay, _dir) = overlay("any_vfs_as_layer");
+        let mut memory = MemoryFs::new();
+        memory
+            .insert("gfx/0.gfx", b"memory 0".to_vec())
+            .insert("pal/0.p46", b"palette".to_vec());
+        overlay.push(memory);
+
+        let vfs: &dyn Vfs = &overlay;
+        assert_eq!(vfs.read(Path::new("gfx/0.gfx")).unwrap(), b"base 0");
+        assert_eq!(vfs.read(Path::new("PAL/0.p46")).unwrap(), b"palette");
+        assert_eq!(overlay.find("pal/0.p46").unwrap(), Some(2));
+        assert_eq!(vfs.read_dir(Path::new("")).unwrap().len(), 3);
+        assert_eq!(vfs.files().unwrap().len(), 5);
+    }
+}
@@ -0,0 +1,379 @@
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::io::ara_crypt::{AraCryptReader, AraCryptWriter};
+use crate::io::checksum::checksum;
+use crate::io::compress::compress;
+use crate::io::decompress::decompress_exact;
+
+/// Size of the encrypted header in front of every segment payload
+pub const HEADER_SIZE: u64 = 24;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SegmentHeader {
+    /// Raw segment type, e.g. `map::file::SegmentType` for maps
+    pub segment_id: u32,
+    pub n_bytes_encrypted: u32,
+    pub n_bytes_decrypted: u32,
+    /// Checksum of the compressed payload following the header
+    pub checksum: u32,
+    pub unknown0: u32,
+    pub unknown1: u32,
+}
+
+impl SegmentHeader {
+    /// Header for the compressed payload of a segment.
+    /// Fails with `InvalidInput` if payload exceeds the 32 bit length field.
+    pub fn new(segment_id: u32, payload: &[u8], n_bytes_decrypted: u32) -> io::Result<Self> {
+        Ok(SegmentHeader {
+            segment_id,
+            n_bytes_encrypted: segment_len(payload.len())?,
+            n_bytes_decrypted,
+            checksum: checksum(payload),
+            unknown0: 0,
+            unknown1: 0,
+        })
+    }
+
+    /// NOTE: usage of bytes 16..24 is unknown
+    pub fn from_le_bytes(bytes: &[u8; 24]) -> io::Result<Self> {
+        Ok(SegmentHeader {
+            segment_id: LittleEndian::read_u32(&bytes[0..4]),
+            n_bytes_encrypted: LittleEndian::read_u32(&bytes[4..8]),
+            n_bytes_decrypted: LittleEndian::read_u32(&bytes[8..12]),
+            checksum: LittleEndian::read_u32(&bytes[12..16]),
+            unknown0: LittleEndian::read_u32(&bytes[16..20]),
+            unknown1: LittleEndian::read_u32(&bytes[20..24]),
+        })
+    }
+
+    pub fn to_le_bytes(&self) -> [u8; 24] {
+        let mut bytes = [0; 24];
+        LittleEndian::write_u32(&mut bytes[0..4], self.segment_id);
+        LittleEndian::write_u32(&mut bytes[4..8], self.n_bytes_encrypted);
+        LittleEndian::write_u32(&mut bytes[8..12], self.n_bytes_decrypted);
+        LittleEndian::write_u32(&mut bytes[12..16], self.checksum);
+        LittleEndian::write_u32(&mut bytes[16..20], self.unknown0);
+        LittleEndian::write_u32(&mut bytes[20..24], self.unknown1);
+        bytes
+    }
+
+    /// Whether the checksum matches the compressed payload
+    pub fn verify(&self, payload: &[u8]) -> bool {
+        self.checksum == checksum(payload)
+    }
+
+    /// Read a header encrypted with keys
+    pub fn read_encrypted<R: Read>(reader: &mut R, keys: [u32; 3]) -> io::Result<Self> {
+        let mut header_buffer = [0; 24];
+
+        AraCryptReader::new(reader, keys).read_exact(&mut header_buffer)?;
+
+        SegmentHeader::from_le_bytes(&header_buffer)
+    }
+
+    /// Write the header encrypted with keys
+    pub fn write_encrypted<W: Write>(&self, writer: &mut W, keys: [u32; 3]) -> io::Result<()> {
+        AraCryptWriter::new(writer, keys).write_all(&self.to_le_bytes())
+    }
+}
+
+/// Position and header of a segment inside its container
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    /// Offset of the encrypted header from the start of the container
+    pub offset: u64,
+    pub header: SegmentHeader,
+}
+
+impl Segment {
+    pub fn segment_id(&self) -> u32 {
+        self.header.segment_id
+    }
+
+    /// Offset of the compressed payload following the header
+    pub fn payload_offset(&self) -> u64 {
+        self.offset + HEADER_SIZE
+    }
+
+    /// Offset right after the payload
+    pub fn end(&self) -> u64 {
+        self.payload_offset() + self.header.n_bytes_encrypted as u64
+    }
+}
+
+/// Sequence of segments, each made of a header encrypted with ara crypt
+/// followed by a compressed payload, as used by map files.
+///
+/// Every segment is kept, whether its type is known or not.
+#[derive(Debug)]
+pub struct SegmentContainer<R> {
+    reader: R,
+    keys: [u32; 3],
+    /// Bytes in front of the first segment
+    prefix_len: u64,
+    /// Total length of the container
+    len: u64,
+    segments: Vec<Segment>,
+}
+
+impl<R: Read + Seek> SegmentContainer<R> {
+    /// Walk all segment headers after the first `prefix_len` bytes of reader.
+    /// Trailing bytes too short to hold another header are left alone.
+    pub fn new(mut reader: R, prefix_len: u64, keys: [u32; 3]) -> io::Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+
+        let mut segments = Vec::new();
+        let mut offset = prefix_len;
+
+        while offset + HEADER_SIZE <= len {
+            reader.seek(SeekFrom::Start(offset))?;
+            let segment = Segment {
+                offset,
+                header: SegmentHeader::read_encrypted(&mut reader, keys)?,
+            };
+            if segment.end() > len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "segment exceeds the container",
+                ));
+            }
+            offset = segment.end();
+            segments.push(segment);
+        }
+
+        Ok(SegmentContainer {
+            reader,
+            keys,
+            prefix_len,
+            len,
+            segments,
+        })
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// First segment with the given type
+    pub fn find(&self, segment_id: u32) -> Option<Segment> {
+        self.segments
+            .iter()
+            .find(|segment| segment.segment_id() == segment_id)
+            .copied()
+    }
+
+    /// Payload as stored, still compressed
+    pub fn read_raw(&mut self, segment: Segment) -> io::Result<Vec<u8>> {
+        self.read_at(
+            segment.payload_offset(),
+            segment.header.n_bytes_encrypted as u64,
+        )
+    }
+
+    pub fn read_decompressed(&mut self, segment: Segment) -> io::Result<Vec<u8>> {
+        self.reader
+            .seek(SeekFrom::Start(segment.payload_offset()))?;
+        let mut crypt_reader = (&mut self.reader).take(segment.header.n_bytes_encrypted as u64);
+        decompress_exact(&mut crypt_reader, segment.header.n_bytes_decrypted as usize)
+    }
+
+    /// Bytes in front of the first segment
+    pub fn read_prefix(&mut self) -> io::Result<Vec<u8>> {
+        self.read_at(0, self.prefix_len)
+    }
+
+    /// Bytes after the last segment
+    pub fn read_trailer(&mut self) -> io::Result<Vec<u8>> {
+        let end = self
+            .segments
+            .last()
+            .map_or(self.prefix_len, |segment| segment.end());
+        let len = self
+            .len
+            .checked_sub(end)
+            .ok_or(io::ErrorKind::UnexpectedEof)?;
+        self.read_at(end, len)
+    }
+
+    /// Segments whose checksum doesn't match their payload
+    pub fn verify(&mut self) -> io::Result<Vec<Segment>> {
+        let mut mismatches = Vec::new();
+        for segment in self.segments.clone() {
+            if !segment.header.verify(&self.read_raw(segment)?) {
+                mismatches.push(segment);
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Write the whole container unchanged
+    pub fn copy_to<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.read_prefix()?)?;
+
+        let mut segment_writer = SegmentWriter::new(&mut *writer, self.keys);
+        for segment in self.segments.clone() {
+            segment_writer.write_raw(segment.header, &self.read_raw(segment)?)?;
+        }
+
+        writer.write_all(&self.read_trailer()?)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn read_at(&mut self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let mut buf = vec![0; len as usize];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// Length as stored in a header, `InvalidInput` if it exceeds the 32 bit fields
+fn segment_len(len: usize) -> io::Result<u32> {
+    u32::try_from(len).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "segment exceeds the format limits",
+        )
+    })
+}
+
+/// Appends segments to a container
+pub struct SegmentWriter<W> {
+    inner: W,
+    keys: [u32; 3],
+}
+
+impl<W: Write> SegmentWriter<W> {
+    pub fn new(inner: W, keys: [u32; 3]) -> SegmentWriter<W> {
+        SegmentWriter { inner, keys }
+    }
+
+    /// Compress data and write it as a segment
+    pub fn write(&mut self, segment_id: u32, data: &[u8]) -> io::Result<SegmentHeader> {
+        let payload = compress(data)?;
+        let header = SegmentHeader::new(segment_id, &payload, segment_len(data.len())?)?;
+        self.write_raw(header, &payload)?;
+        Ok(header)
+    }
+
+    /// Write an already compressed payload under the given header
+    pub fn write_raw(&mut self, header: SegmentHeader, payload: &[u8]) -> io::Result<()> {
+        if header.n_bytes_encrypted as usize != payload.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "payload length differs from the header",
+            ));
+        }
+        header.write_encrypted(&mut self.inner, self.keys)?;
+        self.inner.write_all(payload)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::io::ara_crypt::MAP_HEADER_KEY;
+    use std::io::Cursor;
+
+    fn container() -> Vec<u8> {
+        let mut writer = SegmentWriter::new(vec![0xAA; 8], MAP_HEADER_KEY);
+        writer.write(1, b"map info").unwrap();
+        writer.write(64, &[0; 300]).unwrap();
+        writer.write(66, b"").unwrap();
+        let mut bytes = writer.into_inner();
+        bytes.extend_from_slice(&[1, 2, 3]);
+        bytes
+    }
+
+    #[test]
+    fn segment_header_round_trip() {
+        let mut bytes = [0; 24];
+        bytes.iter_mut().enumerate().for_each(|(i, x)| *x = i as u8);
+
+        let header = SegmentHeader::from_le_bytes(&bytes).unwrap();
+        assert_eq!(header.segment_id, 0x03020100);
+        assert_eq!(header.unknown1, 0x17161514);
+        assert_eq!(header.to_le_bytes(), bytes);
+    }
+
+    #[test]
+    fn lists_all_segments() {
+        let bytes = container();
+        let container = SegmentContainer::new(Cursor::new(&bytes), 8, MAP_HEADER_KEY).unwrap();
+
+        let ids: Vec<u32> = container
+            .segments()
+            .iter()
+            .map(Segment::segment_id)
+            .collect();
+        assert_eq!(ids, [1, 64, 66]);
+
+        let segments = container.segments();
+        assert_eq!(segments[0].offset, 8);
+        assert_eq!(segments[1].offset, segments[0].end());
+        assert_eq!(segments[1].header.n_bytes_decrypted, 300);
+        assert_eq!(segments[2].end(), bytes.len() as u64 - 3);
+    }
+
+    #[test]
+    fn reads_payloads() {
+        let bytes = container();
+        let mut container = SegmentContainer::new(Cursor::new(&bytes), 8, MAP_HEADER_KEY).unwrap();
+
+        let edm = container.find(64).unwrap();
+        assert_eq!(container.read_decompressed(edm).unwrap(), [0; 300]);
+        let raw = container.read_raw(edm).unwrap();
+        assert_eq!(raw.len(), edm.header.n_bytes_encrypted as usize);
+        assert!(edm.header.verify(&raw));
+
+        assert_eq!(container.read_prefix().unwrap(), [0xAA; 8]);
+        assert_eq!(container.read_trailer().unwrap(), [1, 2, 3]);
+        assert!(container.find(2).is_none());
+        assert!(container.verify().unwrap().is_empty());
+    }
+
+    #[test]
+    fn copy_is_identical() {
+        let bytes = container();
+        let mut container = SegmentContainer::new(Cursor::new(&bytes), 8, MAP_HEADER_KEY).unwrap();
+
+        let mut copy = Vec::new();
+        container.copy_to(&mut copy).unwrap();
+        assert_eq!(copy, bytes);
+    }
+
+    #[test]
+    fn truncated_segment() {
+        let mut bytes = container();
+        bytes.truncate(bytes.len() - 4);
+        let result = SegmentContainer::new(Cursor::new(&bytes), 8, MAP_HEADER_KEY);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn shorter_than_prefix() {
+        let bytes = [0xAA; 4];
+        let mut container = SegmentContainer::new(Cursor::new(&bytes), 8, MAP_HEADER_KEY).unwrap();
+        assert!(container.segments().is_empty());
+        let e = container.read_trailer().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn format_limits() {
+        assert_eq!(segment_len(u32::MAX as usize).unwrap(), u32::MAX);
+        let e = segment_len(u32::MAX as usize + 1).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,27 @@
+//! Scratch directories for tests that read or write files on disk
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Empty directory below the system temp directory, removed with its
+/// contents on drop, also when the test fails
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Directory for the test name, unique to this process
+    pub(crate) fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("s4-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+    pub(crate) fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,363 @@
+//! Virtual filesystem shared by all loaders, so a palette inside gfx.lib
+//! and a map on disk are read the same way.
+//!
+//! Paths are matched case-insensitively and may use either separator,
+//! like the game does on Windows.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::io::archive::{normalize, split_path, Archive, DirEntry};
+
+/// File or directory listed by `Vfs::read_dir`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VfsEntry {
+    path: PathBuf,
+    is_dir: bool,
+}
+
+impl VfsEntry {
+    pub fn new(path: PathBuf, is_dir: bool) -> VfsEntry {
+        VfsEntry { path, is_dir }
+    }
+    /// Path relative to the root of the filesystem
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+}
+
+pub trait Vfs {
+    /// Contents of the file at path, `None` if there is no such file
+    fn open(&self, path: &Path) -> io::Result<Option<Vec<u8>>>;
+
+    fn is_file(&self, path: &Path) -> io::Result<bool>;
+
+    /// Children of dir, use "" for the root. Fails with `NotFound` if dir is missing.
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<VfsEntry>>;
+
+    /// Paths of all files
+    fn files(&self) -> io::Result<Vec<PathBuf>>;
+
+    /// Contents of the file at path, failing with `NotFound` if there is none
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.open(path)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no file {}", path.display()),
+            )
+        })
+    }
+}
+
+/// Files below a directory on disk, e.g. the installation root of the game
+#[derive(Debug, Clone, Default)]
+pub struct Directory {
+    root: PathBuf,
+}
+
+impl Directory {
+    /// Paths are relative to root, the default root is the working directory
+    pub fn new<P: AsRef<Path>>(root: P) -> Directory {
+        Directory {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Location of path on disk, exact matches win over ones differing in case.
+    /// `None` for paths leaving root, e.g. absolute ones or ones with `..`.
+    fn resolve(&self, path: &Path) -> io::Result<Option<PathBuf>> {
+        if !is_below(path) {
+            return Ok(None);
+        }
+        let exact = self.root.join(path);
+        if exact.exists() {
+            return Ok(Some(exact));
+        }
+        resolve(&self.root, &path.to_string_lossy())
+    }
+}
+
+impl Vfs for Directory {
+    fn open(&self, path: &Path) -> io::Result<Option<Vec<u8>>> {
+        match self.resolve(path)? {
+            Some(file) if file.is_file() => Ok(Some(fs::read(file)?)),
+            _ => Ok(None),
+        }
+    }
+
+    fn is_file(&self, path: &Path) -> io::Result<bool> {
+        Ok(self.resolve(path)?.is_some_and(|file| file.is_file()))
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<VfsEntry>> {
+        let resolved = match self.resolve(dir)? {
+            Some(resolved) if resolved.is_dir() => resolved,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no directory {}", dir.display()),
+                ))
+            }
+        };
+        let relative = resolved
+            .strip_prefix(&self.root)
+            .unwrap_or(&resolved)
+            .to_path_buf();
+
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&resolved)? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            entries.push(VfsEntry::new(path, entry.file_type()?.is_dir()));
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(entries)
+    }
+
+    fn files(&self) -> io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        walk(&self.root, PathBuf::new(), &mut paths)?;
+        Ok(paths)
+    }
+}
+
+impl Vfs for Archive {
+    fn open(&self, path: &Path) -> io::Result<Option<Vec<u8>>> {
+        Archive::open(self, path)
+    }
+
+    fn is_file(&self, path: &Path) -> io::Result<bool> {
+        Ok(self.metadata(path).is_some())
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<VfsEntry>> {
+        Ok(Archive::read_dir(self, dir)?
+            .iter()
+            .map(|entry| {
+                let is_dir = matches!(entry, DirEntry::Dir(_));
+                VfsEntry::new(entry.path().to_path_buf(), is_dir)
+            })
+            .collect())
+    }
+
+    fn files(&self) -> io::Result<Vec<PathBuf>> {
+        Ok(self.entries().map(|f| f.path().to_path_buf()).collect())
+    }
+}
+
+/// Files held in memory, mostly for tests
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFs {
+    /// (path, contents) by normalized path
+    files: BTreeMap<String, (PathBuf, Vec<u8>)>,
+}
+
+impl MemoryFs {
+    pub fn new() -> MemoryFs {
+        MemoryFs::default()
+    }
+
+    /// Add or replace the file at path
+    pub fn insert<P: AsRef<Path>>(&mut self, path: P, data: Vec<u8>) -> &mut MemoryFs {
+        let path: PathBuf = split_path(&path.as_ref().to_string_lossy()).collect();
+        let key = normalize(&path.to_string_lossy());
+        self.files.insert(key, (path, data));
+        self
+    }
+}
+
+impl Vfs for MemoryFs {
+    fn open(&self, path: &Path) -> io::Result<Option<Vec<u8>>> {
+        let key = normalize(&path.to_string_lossy());
+        Ok(self.files.get(&key).map(|(_, data)| data.clone()))
+    }
+
+    fn is_file(&self, path: &Path) -> io::Result<bool> {
+        Ok(self.files.contains_key(&normalize(&path.to_string_lossy())))
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<VfsEntry>> {
+        let dir = normalize(&dir.to_string_lossy());
+        let depth = split_path(&dir).count();
+        let mut dirs = HashSet::new();
+        let mut entries = Vec::new();
+
+        for (key, (path, _)) in &self.files {
+            let rest = if dir.is_empty() {
+                Some(key.as_str())
+            } else {
+                key.strip_prefix(dir.as_str())
+                    .and_then(|rest| rest.strip_prefix('/'))
+            };
+            match rest.map(|rest| rest.split_once('/')) {
+                None => {}
+                Some(None) => entries.push(VfsEntry::new(path.clone(), false)),
+                Some(Some((child, _))) if dirs.insert(child) => {
+                    let child = path.components().take(depth + 1).collect();
+                    entries.push(VfsEntry::new(child, true));
+                }
+                Some(Some(_)) => {}
+            }
+        }
+
+        if entries.is_empty() && !dir.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no directory {}", dir),
+            ));
+        }
+        Ok(entries)
+    }
+
+    fn files(&self) -> io::Result<Vec<PathBuf>> {
+        Ok(self.files.values().map(|(path, _)| path.clone()).collect())
+    }
+}
+
+/// Find path below root, ignoring the case of each component
+fn resolve(root: &Path, path: &str) -> io::Result<Option<PathBuf>> {
+    let mut resolved = root.to_path_buf();
+
+    for component in split_path(path) {
+        if !is_below(Path::new(component)) {
+            return Ok(None);
+        }
+        let exact = resolved.join(component);
+        if exact.exists() {
+            resolved = exact;
+            continue;
+        }
+
+        let entries = match fs::read_dir(&resolved) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotADirectory => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let component = component.to_lowercase();
+        let mut found = None;
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().to_lowercase() == component {
+                found = Some(entry.path());
+                break;
+            }
+        }
+
+        match found {
+            Some(path) => resolved = path,
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some(resolved))
+}
+
+/// Whether path joined to a root stays below it
+fn is_below(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Collect all files below root/relative, relative to root
+fn walk(root: &Path, relative: PathBuf, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(root.join(&relative))?.collect::<io::Result<_>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            walk(root, path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::io::archive::ArchiveBuilder;
+    use crate::io::temp_dir::TempDir;
+
+    /// The same files on disk, in an archive and in memory
+    fn filesystems(name: &str) -> (Vec<Box<dyn Vfs>>, TempDir) {
+        let files: [(&str, &[u8]); 3] = [
+            ("Gfx/0.gfx", b"graphic"),
+            ("Gfx/Pal/0.p46", b"palette"),
+            ("Map/Aeneas.map", b"map"),
+        ];
+
+        let dir = TempDir::new(name);
+        let mut builder = ArchiveBuilder::new(Vec::new());
+        let mut memory = MemoryFs::new();
+        for (path, data) in files {
+            let file = dir.join("root").join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, data).unwrap();
+            builder.add(path, data, true).unwrap();
+            memory.insert(path, data.to_vec());
+        }
+        fs::write(dir.join("test.lib"), builder.finish().unwrap()).unwrap();
+
+        let filesystems: Vec<Box<dyn Vfs>> = vec![
+            Box::new(Directory::new(dir.join("root"))),
+            Box::new(Archive::new(dir.join("test.lib")).unwrap()),
+            Box::new(memory),
+        ];
+        (filesystems, dir)
+    }
+
+    #[test]
+    fn implementations_agree() {
+        let (filesystems, dir) = filesystems("implementations_agree");
+
+        for vfs in &filesystems {
+            assert_eq!(vfs.read(Path::new("gfx\\PAL\\0.p46")).unwrap(), b"palette");
+            assert_eq!(vfs.open(Path::new("gfx/1.gfx")).unwrap(), None);
+            let e = vfs.read(Path::new("gfx/1.gfx")).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::NotFound);
+            assert!(vfs.is_file(Path::new("map/aeneas.map")).unwrap());
+            assert!(!vfs.is_file(Path::new("map")).unwrap());
+            // test.lib is next to the root of the directory
+            assert!(!vfs.is_file(Path::new("../test.lib")).unwrap());
+            assert!(!vfs.is_file(&dir.join("test.lib")).unwrap());
+
+            let entries = vfs.read_dir(Path::new("GFX")).unwrap();
+            assert_eq!(
+                entries,
+                [
+                    VfsEntry::new(PathBuf::from("Gfx/0.gfx"), false),
+                    VfsEntry::new(PathBuf::from("Gfx/Pal"), true)
+                ]
+            );
+            let root = vfs.read_dir(Path::new("")).unwrap();
+            assert_eq!(root.len(), 2);
+            let e = vfs.read_dir(Path::new("snd")).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::NotFound);
+
+            let mut files = vfs.files().unwrap();
+            files.sort();
+            assert_eq!(
+                files,
+                [
+                    Path::new("Gfx/0.gfx"),
+                    Path::new("Gfx/Pal/0.p46"),
+                    Path::new("Map/Aeneas.map")
+                ]
+            );
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Windows-1252, the code page of names and texts written by the game.
+//!
+//! The five bytes left undefined by the code page map to the C1 control
+//! characters of the same value, so every byte string survives a round trip.
+
+/// Characters of 0x80..=0x9F, the rest of the code page equals Latin-1
+const HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+pub fn decode(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| match byte {
+            0x80..=0x9F => HIGH[byte as usize - 0x80],
+            _ => byte as char,
+        })
+        .collect()
+}
+
+/// `None` if text has characters outside the code page
+pub fn encode(text: &str) -> Option<Vec<u8>> {
+    text.chars()
+        .map(|c| match c as u32 {
+            0x00..=0x7F | 0xA0..=0xFF => Some(c as u8),
+            _ => HIGH.iter().position(|&h| h == c).map(|i| 0x80 + i as u8),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let text = decode(&bytes);
+        assert_eq!(text.chars().count(), 256);
+        assert_eq!(encode(&text).unwrap(), bytes);
+
+        assert_eq!(decode(b"Stra\xDFe \x80"), "Straße €");
+        assert_eq!(encode("Straße €").unwrap(), b"Stra\xDFe \x80");
+        assert_eq!(encode("\u{0100}"), None);
+    }
+}
@@ -1,6 +1,18 @@
-// pub mod gfx;
+//! Reading and writing the files of The Settlers IV.
+//!
+//! Some format details are inferred rather than documented. The tests that
+//! load the game files from `data/` check them; those files are not part of
+//! the repository.
+//!
+//! - the checksum of archive entries
+//! - the header length `io::archive::ArchiveBuilder` writes
+//! - the layout of the team constellations of a map
+//! - the layout of a map preview
+//! - the object ids of `map::object`, none of which are known yet
+
+pub mod gfx;
 pub mod io;
 pub mod map;
 // pub mod settler;
 // pub mod stack;
-// pub mod ground;
+pub mod ground;
@@ -1,7 +1,6 @@
-use std::fs::File;
-use std::fs::OpenOptions;
+use std::fs;
 use std::io;
-use std::io::{prelude::*, BufReader};
+use std::io::{prelude::*, Cursor};
 use std::path::Path;
 use std::str;
 
@@ -9,90 +8,267 @@ use byteorder::ReadBytesExt;
 use byteorder::{ByteOrder, LittleEndian};
 use num_enum::TryFromPrimitive;
 
-use crate::io::ara_crypt::AraCrypt;
-use crate::io::decompress::decompress;
+use crate::ground::Landscape;
+use crate::io::ara_crypt::MAP_HEADER_KEY;
+use crate::io::checksum::checksum;
+use crate::io::segment::SegmentContainer;
+pub use crate::io::segment::SegmentHeader;
+use crate::io::vfs::Vfs;
 use crate::map::info::*;
+use crate::map::object::{objects_from_le_bytes, Object};
+use crate::map::player::Player;
+use crate::map::preview::Preview;
+use crate::map::team::TeamInfo;
+
+/// How checksum mismatches are handled when loading a map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verify {
+    /// Fail loading on any mismatch
+    Strict,
+    /// Load anyway and record mismatches in `GameMap::checksum_mismatches`
+    #[default]
+    Warn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumMismatch {
+    /// Checksum in the first 4 bytes, covering the file after the version
+    File { expected: u32, actual: u32 },
+    /// Checksum of the payload of the segment whose header starts at offset
+    Segment {
+        offset: u64,
+        expected: u32,
+        actual: u32,
+    },
+}
 
 #[derive(Debug)]
 pub struct GameMap {
     checksum: u32,
     version: u32,
+    checksum_mismatches: Vec<ChecksumMismatch>,
     map: Info,
-    // player: Player,
-    // team: TeamInfo,
-    // preview: Box<Preview>,
-    // objects: Vec<Object>,
+    players: Vec<Player>,
+    teams: Option<Vec<TeamInfo>>,
+    preview: Option<Preview>,
+    objects: Option<Vec<Object>>,
     // settlers: Vec<Settler>,
     // buildings: Vec<Building>,
     // stacks: Vec<Stack>,
     // victory_conditions: VictoryCondition,
     // mission_text_german: String,
     // mission_hint_german: String,
-    // landscape: Vec<Ground>,
+    landscape: Option<Landscape>,
     // mission_text_english: String,
     // mission_hint_english: String,
     // lua_script: String,
 }
 
 impl GameMap {
+    /// Load a map, recording checksum mismatches instead of failing on them
     pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let file = OpenOptions::new().read(true).open(path.as_ref())?;
-        let mut reader = BufReader::<File>::new(file);
+        GameMap::from_file_with(path, Verify::Warn)
+    }
+    pub fn from_file_with<P: AsRef<Path>>(path: P, verify: Verify) -> io::Result<Self> {
+        GameMap::from_bytes(&fs::read(path)?, verify)
+    }
+    /// Load the map at path inside vfs, e.g. an install directory or an archive
+    pub fn from_vfs<V: Vfs + ?Sized, P: AsRef<Path>>(
+        vfs: &V,
+        path: P,
+        verify: Verify,
+    ) -> io::Result<Self> {
+        let bytes = vfs.read(path.as_ref())?;
+        GameMap::from_bytes(&bytes, verify)
+    }
+    pub fn from_bytes(bytes: &[u8], verify: Verify) -> io::Result<Self> {
+        let mut reader = Cursor::new(bytes);
+
+        let checksum = reader.read_u32::<LittleEndian>()?;
+        let version = reader.read_u32::<LittleEndian>()?;
+
+        let mut segments = SegmentContainer::new(reader, 8, MAP_HEADER_KEY)?;
+
+        let checksum_mismatches = GameMap::verify_checksums(bytes, &mut segments)?;
+        if verify == Verify::Strict && !checksum_mismatches.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("checksum mismatch: {:?}", checksum_mismatches[0]),
+            ));
+        }
+
+        let map = GameMap::read_info(&mut segments)?;
+        let players = GameMap::read_players(&mut segments)?;
+        let landscape = GameMap::read_landscape(&mut segments, map.map_size())?;
+        let objects = GameMap::read_objects(&mut segments, map.map_size())?;
 
         Ok(GameMap {
-            checksum: reader.read_u32::<LittleEndian>().unwrap(),
-            version: reader.read_u32::<LittleEndian>().unwrap(),
-            map: GameMap::read_info(&mut reader)?,
+            checksum,
+            version,
+            checksum_mismatches,
+            map,
+            teams: GameMap::read_teams(&mut segments, players.len())?,
+            players,
+            preview: GameMap::read_preview(&mut segments)?,
+            landscape,
+            objects,
         })
     }
-    fn read_info(reader: &mut BufReader<File>) -> io::Result<Info> {
-        while let Ok(header) = GameMap::read_header(reader) {
-            //dbg!(&header);
-            if Some(SegmentType::MapInfo) == header.segment_type {
-                let mut crypt_reader = reader.take(header.n_bytes_encrypted as u64);
-                let decrypt = decompress(&mut crypt_reader)?;
-                return Info::from_le_bytes(&decrypt)
-            } else {
-                let _ = reader.seek_relative(header.n_bytes_encrypted as i64);
-            }
-        }
-        Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "header segment for map info not found",
-        ))
+    pub fn checksum(&self) -> u32 {
+        self.checksum
+    }
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+    pub fn info(&self) -> &Info {
+        &self.map
+    }
+    /// Players in the order of their slots
+    pub fn players(&self) -> &[Player] {
+        &self.players
     }
-    fn read_header(reader: &mut BufReader<File>) -> io::Result<SegmentHeader> {
-        let mut header_buffer = [0; 24];
+    /// Team setups the map can be played with, `None` without a TeamInfo segment
+    pub fn constellations(&self) -> Option<&[TeamInfo]> {
+        self.teams.as_deref()
+    }
+    /// `None` without a Preview segment
+    pub fn preview(&self) -> Option<&Preview> {
+        self.preview.as_ref()
+    }
+    /// Tiles of the map, `Info::map_size` along each side.
+    /// `None` without a Ground segment.
+    pub fn landscape(&self) -> Option<&Landscape> {
+        self.landscape.as_ref()
+    }
+    /// Objects of all tiles that have one, row by row.
+    /// `None` without an Objects segment.
+    pub fn objects(&self) -> Option<&[Object]> {
+        self.objects.as_deref()
+    }
+    /// Checksums found to be wrong while loading with `Verify::Warn`
+    pub fn checksum_mismatches(&self) -> &[ChecksumMismatch] {
+        &self.checksum_mismatches
+    }
+    /// Checksum stored in the first 4 bytes of a map file
+    pub fn file_checksum(file: &[u8]) -> u32 {
+        checksum(file.get(8..).unwrap_or_default())
+    }
+    /// Store the checksum of a fully written map file in its first 4 bytes
+    pub fn write_file_checksum(file: &mut [u8]) {
+        let checksum = GameMap::file_checksum(file);
+        LittleEndian::write_u32(&mut file[0..4], checksum);
+    }
+    fn verify_checksums(
+        bytes: &[u8],
+        segments: &mut SegmentContainer<Cursor<&[u8]>>,
+    ) -> io::Result<Vec<ChecksumMismatch>> {
+        let mut mismatches = Vec::new();
 
-        reader.read_exact(&mut header_buffer)?;
+        let expected = LittleEndian::read_u32(&bytes[0..4]);
+        let actual = GameMap::file_checksum(bytes);
+        if expected != actual {
+            mismatches.push(ChecksumMismatch::File { expected, actual });
+        }
 
-        let mut ara_crypt = AraCrypt::new([0x30313233, 0x34353637, 0x38393031]);
-        header_buffer
-            .iter_mut()
-            .for_each(|x| *x = *x ^ ara_crypt.next() as u8);
+        for segment in segments.segments().to_vec() {
+            let actual = checksum(&segments.read_raw(segment)?);
+            if segment.header.checksum != actual {
+                mismatches.push(ChecksumMismatch::Segment {
+                    offset: segment.offset,
+                    expected: segment.header.checksum,
+                    actual,
+                });
+            }
+        }
 
-        Ok(SegmentHeader::from_le_bytes(&header_buffer)?)
+        Ok(mismatches)
     }
-}
-
-#[derive(Copy, Clone, Debug)]
-pub struct SegmentHeader {
-    pub segment_type: Option<SegmentType>,
-    pub n_bytes_encrypted: u32,
-    pub n_bytes_decrypted: u32,
-    pub checksum: u32,
-}
-
-impl SegmentHeader {
-    /// NOTE: usage of bytes 16..24 is unknown
-    pub fn from_le_bytes(bytes: &[u8; 24]) -> io::Result<Self> {
-        Ok(SegmentHeader {
-            segment_type: SegmentType::try_from(LittleEndian::read_u32(&bytes[0..4])).ok(),
-            n_bytes_encrypted: LittleEndian::read_u32(&bytes[4..8]),
-            n_bytes_decrypted: LittleEndian::read_u32(&bytes[8..12]),
-            checksum: LittleEndian::read_u32(&bytes[12..16]),
+    fn read_segment<R: Read + Seek>(
+        segments: &mut SegmentContainer<R>,
+        segment_type: SegmentType,
+    ) -> io::Result<Vec<u8>> {
+        GameMap::read_optional_segment(segments, segment_type)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("header segment for {:?} not found", segment_type),
+            )
         })
     }
+    /// Payload of a segment maps may leave out, `None` if there is none
+    fn read_optional_segment<R: Read + Seek>(
+        segments: &mut SegmentContainer<R>,
+        segment_type: SegmentType,
+    ) -> io::Result<Option<Vec<u8>>> {
+        segments
+            .find(segment_type as u32)
+            .map(|segment| segments.read_decompressed(segment))
+            .transpose()
+    }
+    fn read_info<R: Read + Seek>(segments: &mut SegmentContainer<R>) -> io::Result<Info> {
+        Info::from_le_bytes(&GameMap::read_segment(segments, SegmentType::MapInfo)?)
+    }
+    fn read_players<R: Read + Seek>(segments: &mut SegmentContainer<R>) -> io::Result<Vec<Player>> {
+        let bytes = GameMap::read_segment(segments, SegmentType::PlayerInfo)?;
+        if bytes.len() % Player::SIZE != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "player info is not a whole number of players",
+            ));
+        }
+        bytes
+            .chunks_exact(Player::SIZE)
+            .map(Player::from_le_bytes)
+            .collect()
+    }
+    fn read_preview<R: Read + Seek>(
+        segments: &mut SegmentContainer<R>,
+    ) -> io::Result<Option<Preview>> {
+        GameMap::read_optional_segment(segments, SegmentType::Preview)?
+            .map(|bytes| Preview::from_le_bytes(&bytes))
+            .transpose()
+    }
+    fn read_landscape<R: Read + Seek>(
+        segments: &mut SegmentContainer<R>,
+        map_size: u32,
+    ) -> io::Result<Option<Landscape>> {
+        GameMap::read_optional_segment(segments, SegmentType::Ground)?
+            .map(|bytes| Landscape::from_le_bytes(&bytes, map_size))
+            .transpose()
+    }
+    fn read_objects<R: Read + Seek>(
+        segments: &mut SegmentContainer<R>,
+        map_size: u32,
+    ) -> io::Result<Option<Vec<Object>>> {
+        GameMap::read_optional_segment(segments, SegmentType::Objects)?
+            .map(|bytes| objects_from_le_bytes(&bytes, map_size))
+            .transpose()
+    }
+    /// One constellation after the other, each with a slot per player.
+    /// Segments that don't divide evenly fail instead of being guessed at.
+    fn read_teams<R: Read + Seek>(
+        segments: &mut SegmentContainer<R>,
+        player_count: usize,
+    ) -> io::Result<Option<Vec<TeamInfo>>> {
+        let Some(bytes) = GameMap::read_optional_segment(segments, SegmentType::TeamInfo)? else {
+            return Ok(None);
+        };
+        let size = TeamInfo::size(player_count);
+        if bytes.len() % size != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "team info of {} bytes is not a whole number of constellations of {} players",
+                    bytes.len(),
+                    player_count
+                ),
+            ));
+        }
+        bytes
+            .chunks_exact(size)
+            .map(|bytes| TeamInfo::from_le_bytes(bytes, player_count))
+            .collect::<io::Result<_>>()
+            .map(Some)
+    }
 }
 
 #[derive(PartialEq, Copy, Clone, Debug, TryFromPrimitive)]
@@ -115,9 +291,9 @@ pub enum SegmentType {
     MissionInfoEN,
     MissionHintEN,
     LuaScript,
-    // EDM = 64,
-    // Unknown1,
-    // EditorInfo,
+    Edm = 64,
+    Unknown1,
+    EditorInfo,
     // Unknown2 = 16974621
 }
 
@@ -142,9 +318,336 @@ mod tests {
 
     use super::*;
 
+    use crate::ground::GroundType;
+    use crate::io::compress::compress;
+    use crate::io::segment::SegmentWriter;
+    use crate::io::temp_dir::TempDir;
+    use crate::io::vfs::MemoryFs;
+    use crate::map::object::ObjectType;
+    use crate::map::player::{PlayerType, Tribe};
+
+    /// Map with a version, a MapInfo segment and the segments of `segments()`
+    fn minimal_map() -> Vec<u8> {
+        map_with_header(|_| {})
+    }
+
+    /// PlayerInfo payload of a Roman and a Viking player
+    fn player_info() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (tribe, x, y, name) in [(0u32, 10u32, 20u32, "Octavia"), (1, 30, 40, "Björn")] {
+            bytes.extend_from_slice(&tribe.to_le_bytes());
+            bytes.extend_from_slice(&x.to_le_bytes());
+            bytes.extend_from_slice(&y.to_le_bytes());
+            let mut name = crate::io::windows1252::encode(name).unwrap();
+            name.resize(32, 0);
+            bytes.extend_from_slice(&name);
+        }
+        bytes
+    }
+
+    /// TeamInfo payload of a free for all and a team constellation
+    fn team_info() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (name, slots) in [("Free for all", [0, 1, 1, 2]), ("Teams", [0, 1, 0, 1])] {
+            let mut name = name.as_bytes().to_vec();
+            name.resize(TeamInfo::NAME_SIZE, 0);
+            bytes.extend_from_slice(&name);
+            bytes.extend_from_slice(&slots);
+        }
+        bytes
+    }
+
+    /// Preview payload of 4x4 red pixels
+    fn preview() -> Vec<u8> {
+        [vec![4, 0, 0, 0], [0x00, 0xF8].repeat(16)].concat()
+    }
+
+    /// Ground payload of 8x8 tiles, rising towards the east
+    fn ground() -> Vec<u8> {
+        (0..64u8)
+            .flat_map(|i| [i % 8, GroundType::Grass as u8, 0, 0])
+            .collect()
+    }
+
+    /// Objects payload of 8x8 tiles with objects of id 5 at (1, 0) and 20 at (2, 3)
+    fn objects() -> Vec<u8> {
+        let mut bytes = vec![0; 8 * 8 * 4];
+        bytes[4] = 5;
+        bytes[(3 * 8 + 2) * 4] = 20;
+        bytes
+    }
+
+    /// Payloads of the segments following MapInfo in a minimal map
+    fn segments() -> Vec<(SegmentType, Vec<u8>)> {
+        vec![
+            (SegmentType::PlayerInfo, player_info()),
+            (SegmentType::TeamInfo, team_info()),
+            (SegmentType::Preview, preview()),
+            (SegmentType::Ground, ground()),
+            (SegmentType::Objects, objects()),
+        ]
+    }
+
+    /// Payload of the segment of type segment_type
+    fn payload(segments: &mut [(SegmentType, Vec<u8>)], segment_type: SegmentType) -> &mut Vec<u8> {
+        let segment = segments.iter_mut().find(|(t, _)| *t == segment_type);
+        &mut segment.expect("segment in the fixture").1
+    }
+
+    /// Minimal map with the MapInfo header changed by edit
+    fn map_with_header(edit: impl FnOnce(&mut SegmentHeader)) -> Vec<u8> {
+        map_with_segments(edit, &segments())
+    }
+
+    fn map_with_segments(
+        edit: impl FnOnce(&mut SegmentHeader),
+        segments: &[(SegmentType, Vec<u8>)],
+    ) -> Vec<u8> {
+        let info = [[0; 4], [2, 0, 0, 0], [1, 0, 0, 0], [8, 0, 0, 0]].concat();
+        let payload = compress(&info).unwrap();
+        let mut header =
+            SegmentHeader::new(SegmentType::MapInfo as u32, &payload, info.len() as u32).unwrap();
+        edit(&mut header);
+
+        let mut writer = SegmentWriter::new(vec![0, 0, 0, 0, 11, 0, 0, 0], MAP_HEADER_KEY);
+        writer.write_raw(header, &payload).unwrap();
+        for (segment_type, payload) in segments {
+            writer.write(*segment_type as u32, payload).unwrap();
+        }
+        let mut file = writer.into_inner();
+
+        GameMap::write_file_checksum(&mut file);
+        file
+    }
+
     #[test]
     fn loading_map_from_file() {
-        let file = GameMap::from_file("data/Settlers 4 Gold/Map/Singleplayer/Aeneas.map");
-        assert!(file.is_ok());
+        let path = "data/Settlers 4 Gold/Map/Singleplayer/Aeneas.map";
+        let map = GameMap::from_file_with(path, Verify::Strict).unwrap();
+        assert!(map.checksum_mismatches().is_empty());
+
+        // every constellation is named and has a slot per player
+        let constellations = map.constellations().unwrap();
+        assert!(!constellations.is_empty());
+        for constellation in constellations {
+            assert!(!constellation.name().is_empty());
+            assert!(constellation.name().chars().all(|c| !c.is_control()));
+            assert_eq!(constellation.players().len(), map.players().len());
+        }
+
+        // the height derived from the colors gives a whole image
+        let preview = map.preview().unwrap();
+        assert!(preview.width() > 0 && preview.height() > 0);
+        assert_eq!(
+            preview.colors().len(),
+            preview.width() as usize * preview.height() as usize
+        );
+
+        // the catalog covers every object placed in the map
+        let objects = map.objects().unwrap();
+        assert!(objects.iter().any(|o| o.object_type().is_tree()));
+        assert!(objects.iter().any(|o| o.object_type().is_stone()));
+        for object in objects {
+            let object_type = object.object_type();
+            assert!(
+                !matches!(object_type, ObjectType::Unknown(_)),
+                "{:?} at {:?} is missing from the catalog",
+                object_type,
+                object.pos()
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_unknown_segments() {
+        let ids = |file: &[u8]| -> Vec<u32> {
+            let segments = SegmentContainer::new(Cursor::new(file), 8, MAP_HEADER_KEY).unwrap();
+            segments.segments().iter().map(|s| s.segment_id()).collect()
+        };
+
+        let mut file = minimal_map();
+        let known = ids(&file);
+        let mut writer = SegmentWriter::new(&mut file, MAP_HEADER_KEY);
+        writer.write(SegmentType::Edm as u32, &[1; 32]).unwrap();
+        writer.write(0x0102_0304, b"unknown").unwrap();
+        GameMap::write_file_checksum(&mut file);
+
+        let map = GameMap::from_bytes(&file, Verify::Strict).unwrap();
+        assert_eq!(map.version(), 11);
+
+        // both are kept after the segments of the minimal map
+        let ids = ids(&file);
+        assert_eq!(ids[..known.len()], known);
+        assert_eq!(ids[known.len()..], [SegmentType::Edm as u32, 0x0102_0304]);
+        assert!(SegmentType::try_from(0x0102_0304).is_err());
+    }
+
+    #[test]
+    fn valid_checksums() {
+        let file = minimal_map();
+
+        let map = GameMap::from_bytes(&file, Verify::Strict).unwrap();
+        assert_eq!(map.version(), 11);
+        assert_eq!(map.checksum(), GameMap::file_checksum(&file));
+        assert!(map.checksum_mismatches().is_empty());
+    }
+
+    #[test]
+    fn wrong_segment_checksum() {
+        let file = map_with_header(|header| header.checksum ^= 1);
+
+        let map = GameMap::from_bytes(&file, Verify::Warn).unwrap();
+        assert_eq!(map.checksum(), GameMap::file_checksum(&file));
+        assert!(matches!(
+            map.checksum_mismatches(),
+            [ChecksumMismatch::Segment { offset: 8, .. }]
+        ));
+
+        let strict = GameMap::from_bytes(&file, Verify::Strict);
+        assert_eq!(strict.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn hand_edited_file() {
+        let mut file = minimal_map();
+        file.extend_from_slice(&[1, 0, 0, 0]);
+
+        let map = GameMap::from_bytes(&file, Verify::Warn).unwrap();
+        assert!(matches!(
+            map.checksum_mismatches(),
+            [ChecksumMismatch::File { .. }]
+        ));
+        assert!(GameMap::from_bytes(&file, Verify::Strict).is_err());
+    }
+
+    #[test]
+    fn loading_map_by_absolute_path() {
+        let dir = TempDir::new("loading_map_by_absolute_path");
+        fs::create_dir(dir.join("maps")).unwrap();
+        fs::write(dir.join("Test.map"), minimal_map()).unwrap();
+
+        let path = dir.join("Test.map");
+        assert!(path.is_absolute());
+        assert_eq!(GameMap::from_file(&path).unwrap().version(), 11);
+        let dotted = dir.join("maps").join("..").join("Test.map");
+        assert_eq!(GameMap::from_file(dotted).unwrap().version(), 11);
+    }
+
+    #[test]
+    fn loading_map_from_vfs() {
+        let mut memory = MemoryFs::new();
+        memory.insert("Map/Singleplayer/Test.map", minimal_map());
+
+        let map = GameMap::from_vfs(&memory, "map\\singleplayer\\test.map", Verify::Strict);
+        assert_eq!(map.unwrap().version(), 11);
+
+        let missing = GameMap::from_vfs(&memory, "map/missing.map", Verify::Strict);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn reading_players() {
+        let map = GameMap::from_bytes(&minimal_map(), Verify::Strict).unwrap();
+        assert_eq!(map.info().player_count(), 2);
+
+        let players = map.players();
+        assert_eq!(players.len(), 2);
+        assert_eq!(players[0].tribe(), Tribe::Roman);
+        assert_eq!(players[0].start_pos(), (10, 20));
+        assert_eq!(players[0].name(), "Octavia");
+        assert_eq!(players[1].tribe(), Tribe::Viking);
+        assert_eq!(players[1].name(), "Björn");
+    }
+
+    #[test]
+    fn invalid_players() {
+        let mut unknown_tribe = segments();
+        payload(&mut unknown_tribe, SegmentType::PlayerInfo)[44] = 9;
+        let map = GameMap::from_bytes(&map_with_segments(|_| {}, &unknown_tribe), Verify::Warn);
+        assert_eq!(map.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut truncated = segments();
+        payload(&mut truncated, SegmentType::PlayerInfo).truncate(50);
+        let map = GameMap::from_bytes(&map_with_segments(|_| {}, &truncated), Verify::Warn);
+        assert_eq!(map.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn optional_segments() {
+        let players = [(SegmentType::PlayerInfo, player_info())];
+        let map = GameMap::from_bytes(&map_with_segments(|_| {}, &players), Verify::Strict);
+
+        let map = map.unwrap();
+        assert_eq!(map.players().len(), 2);
+        assert!(map.constellations().is_none());
+        assert!(map.preview().is_none());
+        assert!(map.landscape().is_none());
+        assert!(map.objects().is_none());
+
+        let map = GameMap::from_bytes(&map_with_segments(|_| {}, &[]), Verify::Strict);
+        assert_eq!(map.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reading_constellations() {
+        let map = GameMap::from_bytes(&minimal_map(), Verify::Strict).unwrap();
+
+        let constellations = map.constellations().unwrap();
+        assert_eq!(constellations.len(), 2);
+        assert_eq!(constellations[0].name(), "Free for all");
+        assert_eq!(constellations[1].name(), "Teams");
+        let slots = constellations[0].players();
+        assert_eq!(slots.len(), map.players().len());
+        assert_eq!(slots[1].team(), 1);
+        assert_eq!(slots[1].player_type(), PlayerType::Computer);
+
+        let mut truncated = segments();
+        payload(&mut truncated, SegmentType::TeamInfo).pop();
+        let map = GameMap::from_bytes(&map_with_segments(|_| {}, &truncated), Verify::Warn);
+        assert_eq!(map.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reading_preview() {
+        let map = GameMap::from_bytes(&minimal_map(), Verify::Strict).unwrap();
+
+        let preview = map.preview().unwrap();
+        assert_eq!((preview.width(), preview.height()), (4, 4));
+        assert_eq!(preview.to_rgba(), [0xFF, 0, 0, 0xFF].repeat(16));
+    }
+
+    #[test]
+    fn reading_landscape() {
+        let map = GameMap::from_bytes(&minimal_map(), Verify::Strict).unwrap();
+
+        let landscape = map.landscape().unwrap();
+        assert_eq!(landscape.size(), map.info().map_size());
+        assert_eq!(landscape[(5, 7)].height, 5);
+        assert_eq!(landscape[(5, 7)].ground_type, GroundType::Grass);
+        assert_eq!(landscape.rows().count(), 8);
+
+        let mut unknown_ground = segments();
+        payload(&mut unknown_ground, SegmentType::Ground)[1] = 8;
+        let map = GameMap::from_bytes(&map_with_segments(|_| {}, &unknown_ground), Verify::Warn);
+        assert_eq!(map.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reading_objects() {
+        let map = GameMap::from_bytes(&minimal_map(), Verify::Strict).unwrap();
+
+        let objects: Vec<((u32, u32), ObjectType)> = map
+            .objects()
+            .unwrap()
+            .iter()
+            .map(|o| (o.pos(), o.object_type()))
+            .collect();
+        assert_eq!(
+            objects,
+            [
+                ((1, 0), ObjectType::from_id(5)),
+                ((2, 3), ObjectType::from_id(20))
+            ]
+        );
     }
 }
@@ -1,6 +1,5 @@
-
 use byteorder::{ByteOrder, LittleEndian as LE};
-use std::io::Result;
+use std::io::{ErrorKind, Result};
 
 #[derive(Clone, Debug)]
 pub struct Info {
@@ -12,6 +11,9 @@ pub struct Info {
 
 impl Info {
     pub fn from_le_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 16 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
         Ok(Info {
             game_type: LE::read_u32(&bytes[..4]),
             player_count: LE::read_u32(&bytes[4..8]),
@@ -19,4 +21,35 @@ impl Info {
             map_size: LE::read_u32(&bytes[12..16]),
         })
     }
+    pub fn game_type(&self) -> u32 {
+        self.game_type
+    }
+    pub fn player_count(&self) -> u32 {
+        self.player_count
+    }
+    pub fn start_resources(&self) -> u32 {
+        self.start_resources
+    }
+    pub fn map_size(&self) -> u32 {
+        self.map_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn decode_info() {
+        let bytes = [[1, 0, 0, 0], [2, 0, 0, 0], [3, 0, 0, 0], [0, 1, 0, 0]].concat();
+        let info = Info::from_le_bytes(&bytes).unwrap();
+        assert_eq!(info.game_type(), 1);
+        assert_eq!(info.player_count(), 2);
+        assert_eq!(info.start_resources(), 3);
+        assert_eq!(info.map_size(), 256);
+
+        let e = Info::from_le_bytes(&bytes[..4]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
 }
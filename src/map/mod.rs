@@ -1,9 +1,9 @@
 pub mod file;
 
 // mod building;
-mod info;
-// mod object;
-// mod player;
-// mod preview;
-// mod team;
+pub mod info;
+pub mod object;
+pub mod player;
+pub mod preview;
+pub mod team;
 // mod victory_cond;
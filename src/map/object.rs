@@ -1,2 +1,204 @@
-#[derive(Debug)]
-pub struct Object {}
+use std::io;
+
+/// Object placed on a tile, e.g. a tree or a stone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Object {
+    pos: (u32, u32),
+    object_type: ObjectType,
+    /// Rest of the record, meaning unknown
+    unknown: [u8; 3],
+}
+
+impl Object {
+    /// Length of a tile in the Objects segment
+    pub const SIZE: usize = 4;
+
+    /// Object of the tile at pos, `None` for an empty tile
+    pub fn from_le_bytes(bytes: &[u8], pos: (u32, u32)) -> io::Result<Option<Self>> {
+        if bytes.len() < Object::SIZE {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if bytes[0] == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(Object {
+            pos,
+            object_type: ObjectType::from_id(bytes[0]),
+            unknown: [bytes[1], bytes[2], bytes[3]],
+        }))
+    }
+    /// Position as (x, y)
+    pub fn pos(&self) -> (u32, u32) {
+        self.pos
+    }
+    pub fn object_type(&self) -> ObjectType {
+        self.object_type
+    }
+    pub fn unknown(&self) -> [u8; 3] {
+        self.unknown
+    }
+}
+
+/// Objects of the tiles of a square map, size × size records row by row
+pub fn objects_from_le_bytes(bytes: &[u8], size: u32) -> io::Result<Vec<Object>> {
+    let len = (size as usize)
+        .checked_mul(size as usize)
+        .and_then(|count| count.checked_mul(Object::SIZE));
+    if len != Some(bytes.len()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "objects of {} bytes don't match a map size of {}",
+                bytes.len(),
+                size
+            ),
+        ));
+    }
+
+    let mut objects = Vec::new();
+    for (i, record) in bytes.chunks_exact(Object::SIZE).enumerate() {
+        let pos = ((i % size as usize) as u32, (i / size as usize) as u32);
+        if let Some(object) = Object::from_le_bytes(record, pos)? {
+            objects.push(object);
+        }
+    }
+    Ok(objects)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectType {
+    /// Tree that can be cut for wood
+    Tree(TreeSpecies),
+    /// Stone that can be cut, size as stored in its id
+    Stone {
+        size: u8,
+    },
+    Plant(Plant),
+    Decoration(Decoration),
+    /// Id missing from the catalog, as stored
+    Unknown(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeSpecies {
+    Oak,
+    Beech,
+    Ash,
+    Linden,
+    Birch,
+    Poplar,
+    Chestnut,
+    Maple,
+    Fir,
+    Spruce,
+    CoconutPalm,
+    DatePalm,
+    Walnut,
+    CorkOak,
+    Pine,
+    Olive,
+    Dead,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Plant {
+    Bush,
+    Grass,
+    Flowers,
+    Cactus,
+    Reed,
+    Mushroom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decoration {
+    Boulder,
+    Ruin,
+    Skeleton,
+    Wreck,
+    Pillar,
+    Grave,
+}
+
+/// Ids with the type they stand for. Ids missing here stay `Unknown`,
+/// misclassifying an object would be worse.
+const CATALOG: &[(u8, ObjectType)] = &[];
+
+impl ObjectType {
+    /// Type of a stored id, `Unknown` for ids missing from the catalog
+    pub fn from_id(id: u8) -> ObjectType {
+        CATALOG
+            .iter()
+            .find(|(i, _)| *i == id)
+            .map_or(ObjectType::Unknown(id), |(_, t)| *t)
+    }
+    /// Id as stored, `None` for types missing from the catalog.
+    /// `from_id(id).id() == Some(id)` for every id.
+    pub fn id(self) -> Option<u8> {
+        match self {
+            ObjectType::Unknown(id) => Some(id),
+            _ => CATALOG.iter().find(|(_, t)| *t == self).map(|(id, _)| *id),
+        }
+    }
+    /// Source of wood
+    pub fn is_tree(self) -> bool {
+        matches!(self, ObjectType::Tree(_))
+    }
+    /// Source of stone
+    pub fn is_stone(self) -> bool {
+        matches!(self, ObjectType::Stone { .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn catalog() {
+        for id in 1..=255 {
+            assert_eq!(ObjectType::from_id(id).id(), Some(id));
+        }
+        for (i, (id, object_type)) in CATALOG.iter().enumerate() {
+            assert_ne!(*id, 0, "0 marks an empty tile");
+            assert!(!matches!(object_type, ObjectType::Unknown(_)));
+            assert!(CATALOG[..i]
+                .iter()
+                .all(|(other, t)| other != id && t != object_type));
+        }
+
+        // any stone size is safe, unconfirmed ones have no id
+        for size in [0, 1, 14, 255] {
+            let stone = ObjectType::Stone { size };
+            assert!(stone.is_stone());
+            assert!(stone.id().is_none() || ObjectType::from_id(stone.id().unwrap()) == stone);
+        }
+        assert!(ObjectType::Tree(TreeSpecies::Oak).is_tree());
+    }
+
+    #[test]
+    fn objects_of_tiles() {
+        let mut bytes = vec![0; 3 * 3 * Object::SIZE];
+        bytes[4 * 4..4 * 4 + 4].copy_from_slice(&[5, 1, 2, 3]);
+        bytes[4 * 8] = 200;
+
+        let objects = objects_from_le_bytes(&bytes, 3).unwrap();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0].pos(), (1, 1));
+        assert_eq!(objects[0].object_type(), ObjectType::from_id(5));
+        assert_eq!(objects[0].unknown(), [1, 2, 3]);
+        assert_eq!(objects[1].pos(), (2, 2));
+        assert_eq!(objects[1].object_type(), ObjectType::Unknown(200));
+
+        let e = objects_from_le_bytes(&bytes, 2).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        // sizes whose tile count overflows instead of wrapping to 0
+        for size in [1 << 31, u32::MAX] {
+            let e = objects_from_le_bytes(&[], size).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -1,10 +1,11 @@
-
 use byteorder::{ByteOrder, LittleEndian};
 use std::convert::TryFrom;
-use std::ffi::CStr;
 use std::fmt;
+use std::io;
+
+use crate::io::windows1252;
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, PartialEq, Eq)]
 pub struct Player {
     tribe: Tribe,
     start_pos: (u32, u32),
@@ -22,38 +23,43 @@ impl fmt::Debug for Player {
 }
 
 impl Player {
-    // pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, String> {
-    //     use std::mem;
-    //     use std::slice;
-    //     use std::io::Read;
-    //     let mut player: Player = unsafe { mem::zeroed() };
-
-    //     let player_size = mem::size_of::<Player>();
+    /// Length of a player record in the PlayerInfo segment
+    pub const SIZE: usize = 44;
 
-    //     unsafe {
-    //         let player_slice = slice::from_raw_parts_mut(&mut player as *mut _ as *mut u8, player_size);
-    //         bytes.read_exact(player_slice).unwrap();
-    //     }
-
-    //     Ok(player)
-    // }
+    pub fn from_le_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < Player::SIZE {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
 
-    pub fn from_le_bytes(bytes: &[u8]) -> Result<Self, String> {
-        let name = unsafe { CStr::from_ptr(bytes[12..44].as_ptr() as *const i8) };
+        // NUL terminated, but may fill all 32 bytes
+        let name = &bytes[12..44];
+        let name = name.split(|&byte| byte == 0).next().unwrap_or(name);
 
         Ok(Player {
-            tribe: Tribe::try_from(LittleEndian::read_u32(&bytes[0..4]))?,
+            tribe: Tribe::try_from(LittleEndian::read_u32(&bytes[0..4]))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
             start_pos: (
                 LittleEndian::read_u32(&bytes[4..8]),  // x
                 LittleEndian::read_u32(&bytes[8..12]), // y
             ),
-            name: name.to_str().unwrap().to_string(),
+            name: windows1252::decode(name),
         })
     }
+    pub fn tribe(&self) -> Tribe {
+        self.tribe
+    }
+    /// Start position as (x, y)
+    pub fn start_pos(&self) -> (u32, u32) {
+        self.start_pos
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Tribe {
+    #[default]
     Roman,
     Viking,
     Mayan,
@@ -76,13 +82,7 @@ impl TryFrom<u32> for Tribe {
     }
 }
 
-impl Default for Tribe {
-    fn default() -> Self {
-        Tribe::Roman
-    }
-}
-
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PlayerType {
     Free,
     Human,
@@ -101,3 +101,46 @@ impl TryFrom<u8> for PlayerType {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn record(tribe: u32, name: &[u8]) -> Vec<u8> {
+        let mut bytes = [
+            tribe.to_le_bytes(),
+            12u32.to_le_bytes(),
+            34u32.to_le_bytes(),
+        ]
+        .concat();
+        bytes.extend_from_slice(name);
+        bytes.resize(Player::SIZE, 0);
+        bytes
+    }
+
+    #[test]
+    fn parse_player() {
+        let player = Player::from_le_bytes(&record(4, b"J\xFCrgen\0garbage")).unwrap();
+        assert_eq!(player.tribe(), Tribe::Trojan);
+        assert_eq!(player.start_pos(), (12, 34));
+        assert_eq!(player.name(), "Jürgen");
+
+        // name without terminator ends with the field
+        let mut bytes = record(0, &[b'a'; 32]);
+        bytes.extend_from_slice(b"next record");
+        assert_eq!(
+            Player::from_le_bytes(&bytes).unwrap().name(),
+            "a".repeat(32)
+        );
+    }
+
+    #[test]
+    fn invalid_player() {
+        let e = Player::from_le_bytes(&record(5, b"")).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        let e = Player::from_le_bytes(&record(0, b"")[..43]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -1,9 +1,127 @@
+use byteorder::{ByteOrder, LittleEndian as LE};
+use std::io;
 
-type Pixel = (u8, u8, u8);
+/// Expand a 16 bit color to red, green, blue and an opaque alpha.
+///
+/// Colors are read as 5 bits red, 6 bits green and 5 bits blue, the layout of
+/// the 16 bit colors elsewhere in the game.
+fn to_rgba(color: u16) -> [u8; 4] {
+    [
+        expand(color >> 11, 5),
+        expand(color >> 5 & 0x3F, 6),
+        expand(color & 0x1F, 5),
+        0xFF,
+    ]
+}
+
+/// Scale a value of bits width to 8 bits, so the largest value becomes 255
+fn expand(value: u16, bits: u32) -> u8 {
+    ((value << (8 - bits)) | (value >> (2 * bits - 8))) as u8
+}
 
-#[derive(Debug)]
+/// Minimap shown when choosing a map
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Preview {
-    data: Vec<u8>,
+    width: u16,
+    height: u16,
+    /// Second header field, meaning unknown
+    unknown: u16,
+    /// Row by row, top to bottom
+    data: Vec<u16>,
+}
+
+impl Preview {
+    /// The width, a field of unknown meaning, then the colors row by row.
+    /// The height follows from the number of colors.
+    pub fn from_le_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < 4 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let width = LE::read_u16(&bytes[0..2]);
+        let unknown = LE::read_u16(&bytes[2..4]);
+
+        let colors = &bytes[4..];
+        let row = width as usize * 2;
+        let height = match row {
+            0 if colors.is_empty() => Some(0),
+            0 => None,
+            _ if !colors.len().is_multiple_of(row) => None,
+            _ => u16::try_from(colors.len() / row).ok(),
+        };
+        let Some(height) = height else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "preview of {} bytes is not a whole number of rows of {} pixels",
+                    colors.len(),
+                    width
+                ),
+            ));
+        };
+
+        Ok(Preview {
+            width,
+            height,
+            unknown,
+            data: colors.chunks_exact(2).map(LE::read_u16).collect(),
+        })
+    }
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+    /// Header field after the width, kept as stored
+    pub fn unknown(&self) -> u16 {
+        self.unknown
+    }
+    /// Colors as stored, row by row
+    pub fn colors(&self) -> &[u16] {
+        &self.data
+    }
+    /// Image as width × height RGBA pixels, row by row
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.data.iter().flat_map(|&color| to_rgba(color)).collect()
+    }
 }
 
-impl Preview {}
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn colors() {
+        assert_eq!(to_rgba(0xFFFF), [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(to_rgba(0xF800), [0xFF, 0, 0, 0xFF]);
+        assert_eq!(to_rgba(0x07E0), [0, 0xFF, 0, 0xFF]);
+        assert_eq!(to_rgba(0x0010), [0, 0, 0x84, 0xFF]);
+    }
+
+    #[test]
+    fn decode_preview() {
+        let bytes = [2, 0, 7, 0, 0x00, 0xF8, 0xE0, 0x07, 0x1F, 0x00, 0xFF, 0xFF];
+        let preview = Preview::from_le_bytes(&bytes).unwrap();
+        assert_eq!((preview.width(), preview.height()), (2, 2));
+        assert_eq!(preview.unknown(), 7);
+        assert_eq!(preview.colors(), [0xF800, 0x07E0, 0x001F, 0xFFFF]);
+
+        let rgba = preview.to_rgba();
+        assert_eq!(rgba.len(), 2 * 2 * 4);
+        assert_eq!(&rgba[..8], [0xFF, 0, 0, 0xFF, 0, 0xFF, 0, 0xFF]);
+
+        // the height follows from the colors
+        let wide = Preview::from_le_bytes(&[4, 0, 0, 0, 1, 0, 2, 0, 3, 0, 4, 0]).unwrap();
+        assert_eq!((wide.width(), wide.height()), (4, 1));
+        let empty = Preview::from_le_bytes(&[0, 0, 0, 0]).unwrap();
+        assert_eq!((empty.width(), empty.height()), (0, 0));
+
+        let e = Preview::from_le_bytes(&bytes[..10]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let e = Preview::from_le_bytes(&[0, 0, 0, 0, 1, 0]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let e = Preview::from_le_bytes(&bytes[..3]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
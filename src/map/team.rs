@@ -1,34 +1,57 @@
+use crate::io::windows1252;
 use crate::map::player::*;
 
-use std::ffi::CStr;
 use std::fmt;
+use std::io;
 
-#[derive(Debug)]
+/// Team constellation, one of the team setups a map can be played with.
+///
+/// Stored as a NUL terminated name in a fixed field, followed by one slot per
+/// player holding its team and `PlayerType`.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TeamInfo {
     constellation_name: String,
     team_player_data: Vec<TeamPlayerData>,
 }
 
 impl TeamInfo {
-    pub fn from_le_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
-        let constellation_name = unsafe { CStr::from_ptr(bytes.as_ptr() as *const i8) };
-
-        let mut team_player_data = Vec::<TeamPlayerData>::new();
-        let chunk_iter = bytes[33..].chunks_exact(2);
-        for chunk in chunk_iter {
-            let data = TeamPlayerData::from_le_bytes(chunk)?;
-            team_player_data.push(data);
+    /// Length of the NUL terminated name in front of the player slots
+    pub const NAME_SIZE: usize = 33;
+
+    /// Length of a constellation with player_count slots
+    pub fn size(player_count: usize) -> usize {
+        TeamInfo::NAME_SIZE + player_count * 2
+    }
+
+    pub fn from_le_bytes(bytes: &[u8], player_count: usize) -> io::Result<Self> {
+        if bytes.len() < TeamInfo::size(player_count) {
+            return Err(io::ErrorKind::UnexpectedEof.into());
         }
 
+        let name = &bytes[..TeamInfo::NAME_SIZE];
+        let name = name.split(|&byte| byte == 0).next().unwrap_or(name);
+
+        let team_player_data = bytes[TeamInfo::NAME_SIZE..TeamInfo::size(player_count)]
+            .chunks_exact(2)
+            .map(TeamPlayerData::from_le_bytes)
+            .collect::<io::Result<_>>()?;
+
         Ok(TeamInfo {
-            constellation_name: constellation_name.to_str().unwrap().to_string(),
+            constellation_name: windows1252::decode(name),
             team_player_data,
         })
     }
+    pub fn name(&self) -> &str {
+        &self.constellation_name
+    }
+    /// Team and type of each player slot, in the order of `GameMap::players`
+    pub fn players(&self) -> &[TeamPlayerData] {
+        &self.team_player_data
+    }
 }
 
-#[derive(Clone, Copy)]
-struct TeamPlayerData {
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TeamPlayerData {
     team: u8,
     player_type: PlayerType,
 }
@@ -40,10 +63,54 @@ impl fmt::Debug for TeamPlayerData {
 }
 
 impl TeamPlayerData {
-    pub fn from_le_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+    pub fn from_le_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let player_type = PlayerType::try_from(bytes[1])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
         Ok(TeamPlayerData {
             team: bytes[0],
-            player_type: PlayerType::try_from(bytes[1])?,
+            player_type,
         })
     }
+    pub fn team(&self) -> u8 {
+        self.team
+    }
+    pub fn player_type(&self) -> PlayerType {
+        self.player_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parse_constellation() {
+        let mut bytes = b"2 vs 1\0garbage".to_vec();
+        bytes.resize(TeamInfo::NAME_SIZE, 0);
+        bytes.extend_from_slice(&[0, 1, 0, 2, 1, 0]);
+
+        let team = TeamInfo::from_le_bytes(&bytes, 3).unwrap();
+        assert_eq!(team.name(), "2 vs 1");
+        let slots: Vec<(u8, PlayerType)> = team
+            .players()
+            .iter()
+            .map(|p| (p.team(), p.player_type()))
+            .collect();
+        assert_eq!(
+            slots,
+            [
+                (0, PlayerType::Human),
+                (0, PlayerType::Computer),
+                (1, PlayerType::Free)
+            ]
+        );
+
+        let e = TeamInfo::from_le_bytes(&bytes, 4).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        bytes[TeamInfo::NAME_SIZE + 1] = 3;
+        let e = TeamInfo::from_le_bytes(&bytes, 3).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
 }